@@ -0,0 +1,34 @@
+// Sends the domain of the active tab to septem-native-host
+// whenever the tab, its url or the focused window changes
+const port = chrome.runtime.connectNative("septem");
+let last;
+
+function send(tab) {
+  let domain = null;
+  if (tab && tab.url) {
+    try {
+      const url = new URL(tab.url);
+      if (url.protocol === "http:" || url.protocol === "https:") {
+        domain = url.hostname.replace(/^www\./, "");
+      }
+    } catch (e) {}
+  }
+
+  if (domain !== last) {
+    last = domain;
+    port.postMessage({ domain });
+  }
+}
+
+function sendActive() {
+  chrome.tabs.query({ active: true, lastFocusedWindow: true }, (tabs) => send(tabs[0]));
+}
+
+chrome.tabs.onActivated.addListener(sendActive);
+chrome.tabs.onUpdated.addListener((id, change, tab) => {
+  if (tab.active && change.url) {
+    sendActive();
+  }
+});
+chrome.windows.onFocusChanged.addListener(sendActive);
+sendActive();
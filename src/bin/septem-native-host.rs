@@ -0,0 +1,112 @@
+// Native messaging host for the Septem browser extension.
+//
+// The browser starts this with the extension's messages on stdin, each one
+// a native endian u32 length followed by that many bytes of JSON. Every
+// {"domain": "docs.rs"} is forwarded to the running daemon as one line of
+// JSON and answered with {"ok": true} or {"ok": false, "error": "..."}.
+//
+// It can be tried without a browser by piping framed messages into it:
+//   printf '\x15\x00\x00\x00{"domain": "docs.rs"}' | septem-native-host
+
+use septem::config::dirs::{self, BROWSER_SOCKET};
+
+use std::io::{self, prelude::*};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+// Browsers refuse messages to the extension over 1 MB
+const MAX_REPLY: usize = 1024 * 1024;
+
+#[derive(Deserialize, Serialize, Debug)]
+struct TabMessage {
+    domain: Option<String>,
+}
+
+// The same directory the daemon listens in, tabs aren't sent
+// to a socket someone else could be listening on
+fn socket_path() -> io::Result<PathBuf> {
+    let runtime = dirs::runtime_dir();
+    dirs::check_private(&runtime)?;
+    Ok(runtime.join(BROWSER_SOCKET))
+}
+
+// None once the browser closes stdin
+fn read_message(input: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut len = [0; 4];
+    match input.read_exact(&mut len) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+
+    let mut message = vec![0; u32::from_ne_bytes(len) as usize];
+    input.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+fn write_message(output: &mut impl Write, message: &serde_json::Value) -> io::Result<()> {
+    let message = serde_json::to_vec(message)?;
+    if message.len() > MAX_REPLY {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "reply is too big for the browser",
+        ));
+    }
+
+    output.write_all(&(message.len() as u32).to_ne_bytes())?;
+    output.write_all(&message)?;
+    output.flush()
+}
+
+struct Daemon {
+    stream: Option<UnixStream>,
+}
+
+impl Daemon {
+    // The daemon may be restarted while the browser keeps us running,
+    // so a failed write reconnects once before giving up
+    fn forward(&mut self, tab: &TabMessage) -> io::Result<()> {
+        let mut line = serde_json::to_vec(tab)?;
+        line.push(b'\n');
+
+        for _ in 0..2 {
+            if self.stream.is_none() {
+                self.stream = Some(UnixStream::connect(socket_path()?)?);
+            }
+            if let Some(s) = &mut self.stream {
+                match s.write_all(&line) {
+                    Ok(()) => return Ok(()),
+                    Err(_) => self.stream = None,
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::BrokenPipe,
+            "lost the connection to septem",
+        ))
+    }
+}
+
+fn main() -> io::Result<()> {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let mut input = stdin.lock();
+    let mut output = stdout.lock();
+    let mut daemon = Daemon { stream: None };
+
+    while let Some(message) = read_message(&mut input)? {
+        let reply = match serde_json::from_slice::<TabMessage>(&message) {
+            Ok(tab) => match daemon.forward(&tab) {
+                Ok(()) => json!({ "ok": true }),
+                Err(e) => json!({ "ok": false, "error": e.to_string() }),
+            },
+            Err(e) => json!({ "ok": false, "error": e.to_string() }),
+        };
+        write_message(&mut output, &reply)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,306 @@
+use crate::config::{Config, ConfigError};
+use crate::server::{check_config, hms, print_report, request, week_of, Server, ServerError};
+
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{Duration as Days, Local, NaiveDate};
+use clap::{ArgGroup, Parser, Subcommand};
+use serde_json::{json, Value};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum CliError {
+    #[error("{0}")]
+    ConfigError(#[from] ConfigError),
+
+    #[error("{0}")]
+    ServerError(#[from] ServerError),
+
+    #[error("Couldn't reach the daemon, is it running?\n{0}")]
+    DaemonError(#[from] io::Error),
+
+    #[error("The daemon refused: {0}")]
+    RefusedError(String),
+}
+
+pub type CliResult<T> = Result<T, CliError>;
+
+#[derive(Parser, Debug)]
+#[command(version, about = "A weekly productivity tracker")]
+pub struct Cli {
+    /// Config file to use instead of $XDG_CONFIG_HOME/Septem/septem.toml
+    #[arg(long, global = true)]
+    config: Option<String>,
+
+    /// Directory to keep the data in instead of share_directory
+    #[arg(long, global = true)]
+    data_dir: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Track the active window, the default without a command
+    Daemon,
+    /// Show what the running daemon is recording and today's totals
+    Status,
+    /// Print a report of the recorded data, this week's by default
+    #[command(group(ArgGroup::new("days").args(["week", "day", "range"])))]
+    Report {
+        /// The week the date is in
+        #[arg(long)]
+        week: Option<NaiveDate>,
+        /// A single day
+        #[arg(long)]
+        day: Option<NaiveDate>,
+        /// The days from one date to another, ie 2024-01-01..2024-01-15
+        #[arg(long)]
+        range: Option<DateRange>,
+    },
+    /// Stop recording, for a duration like 90s, 15m or 1h30m if given
+    Pause {
+        #[arg(value_parser = parse_duration)]
+        duration: Option<Duration>,
+    },
+    /// Start recording again after a pause
+    Resume,
+    /// Switch to a profile until another is chosen, or back to the schedule
+    #[command(group(ArgGroup::new("choice").required(true).args(["name", "schedule"])))]
+    Profile {
+        name: Option<String>,
+        /// Follow the schedule again
+        #[arg(long)]
+        schedule: bool,
+    },
+    /// Check the config for errors without starting the daemon
+    CheckConfig,
+}
+
+#[derive(Clone, Debug)]
+struct DateRange(NaiveDate, NaiveDate);
+
+impl FromStr for DateRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s
+            .split_once("..")
+            .ok_or_else(|| "expected FROM..TO".to_owned())?;
+        let parse = |d: &str| d.parse::<NaiveDate>().map_err(|e| format!("{}: {}", d, e));
+        let (from, to) = (parse(from)?, parse(to)?);
+        if from > to {
+            return Err(format!("{} is after {}", from, to));
+        }
+        Ok(DateRange(from, to))
+    }
+}
+
+// Hours, minutes and seconds like 1h30m, a bare number is minutes
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let too_long = || format!("{} is too long", s);
+    if let Ok(minutes) = s.parse::<u64>() {
+        return Ok(Duration::from_secs(
+            minutes.checked_mul(60).ok_or_else(too_long)?,
+        ));
+    }
+
+    let mut secs: u64 = 0;
+    let mut number = String::new();
+    for c in s.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            }
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return Err(format!("unknown unit {} in {}", c, s)),
+        };
+        let n: u64 = match number.is_empty() {
+            true => return Err(format!("missing a number before {} in {}", c, s)),
+            false => number.parse().map_err(|_| too_long())?,
+        };
+        secs = n
+            .checked_mul(unit)
+            .and_then(|n| secs.checked_add(n))
+            .ok_or_else(too_long)?;
+        number.clear();
+    }
+
+    if !number.is_empty() || secs == 0 {
+        return Err(format!("{} isn't a duration like 90s, 15m or 1h30m", s));
+    }
+    Ok(Duration::from_secs(secs))
+}
+
+impl Cli {
+    fn load_config(&self) -> CliResult<Config> {
+        let mut config = Config::new(self.config.clone())?;
+        if let Some(d) = &self.data_dir {
+            config.set_share(d.to_owned());
+        }
+        Ok(config)
+    }
+
+    fn ask(&self, command: Value) -> CliResult<Value> {
+        let runtime = Config::runtime()?;
+        let answer = request(Path::new(&runtime), &command)?;
+        if answer["ok"].as_bool() == Some(true) {
+            Ok(answer)
+        } else {
+            let error = answer["error"].as_str().unwrap_or("no reason was given");
+            Err(CliError::RefusedError(error.to_owned()))
+        }
+    }
+
+    fn status(&self) -> CliResult<()> {
+        let status = self.ask(json!({ "command": "status" }))?;
+
+        if status["paused"].as_bool() == Some(true) {
+            println!("Paused");
+        } else if status["idle"].as_bool() == Some(true) {
+            println!("Idle");
+        }
+        if let Some(profile) = status["profile"].as_str() {
+            match status["chosen"].as_bool() {
+                Some(true) => println!("Profile: {}", profile),
+                _ => println!("Profile: {} (scheduled)", profile),
+            }
+        }
+        match status["app"].as_str() {
+            Some(app) => println!(
+                "Current: {} ({}, score {:.2})",
+                app,
+                status["category"].as_str().unwrap_or_default(),
+                status["score"].as_f64().unwrap_or_default()
+            ),
+            None => println!("Current: nothing"),
+        }
+
+        let mut today: Vec<(&String, u64)> = status["today"]
+            .as_object()
+            .map(|t| {
+                t.iter()
+                    .map(|(n, s)| (n, s.as_u64().unwrap_or(0)))
+                    .collect()
+            })
+            .unwrap_or_default();
+        today.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+        println!("Today: {}", hms(today.iter().map(|t| t.1).sum()));
+        for (name, secs) in today {
+            println!("  {}: {}", name, hms(secs));
+        }
+        Ok(())
+    }
+
+    fn report(
+        &self,
+        week: Option<NaiveDate>,
+        day: Option<NaiveDate>,
+        range: Option<DateRange>,
+    ) -> CliResult<()> {
+        let (from, to) = match (week, day, range) {
+            (_, Some(d), _) => (d, d),
+            (_, _, Some(DateRange(from, to))) => (from, to),
+            (w, _, _) => {
+                let week = week_of(w.unwrap_or_else(|| Local::now().date_naive()));
+                (week, week + Days::days(6))
+            }
+        };
+
+        let config = self.load_config()?;
+        let share = config.share()?;
+        print_report(
+            Path::new(&share),
+            &config.recorder_config(),
+            &config.budget_config(),
+            from,
+            to,
+        )
+        .map_err(ServerError::from)?;
+        Ok(())
+    }
+
+    pub async fn run(self) -> CliResult<()> {
+        match self.command {
+            None | Some(Command::Daemon) => {
+                Server::new(self.config.clone(), self.data_dir.clone())?.await?;
+                println!("Main End");
+            }
+            Some(Command::Status) => self.status()?,
+            Some(Command::Report {
+                week,
+                day,
+                ref range,
+            }) => self.report(week, day, range.clone())?,
+            Some(Command::Pause { duration }) => {
+                self.ask(json!({
+                    "command": "pause",
+                    "seconds": duration.map(|d| d.as_secs()),
+                }))?;
+            }
+            Some(Command::Resume) => {
+                self.ask(json!({ "command": "resume" }))?;
+            }
+            Some(Command::Profile { ref name, .. }) => {
+                self.ask(json!({ "command": "profile", "name": name }))?;
+            }
+            Some(Command::CheckConfig) => {
+                let config = self.load_config()?;
+                check_config(&config)?;
+                println!("The config is valid");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_take_units_or_minutes() {
+        let secs = |s| parse_duration(s).map(|d| d.as_secs());
+        assert_eq!(secs("15"), Ok(15 * 60));
+        assert_eq!(secs("90s"), Ok(90));
+        assert_eq!(secs("1h30m"), Ok(90 * 60));
+        assert_eq!(secs("2h5s"), Ok(2 * 3600 + 5));
+        assert!(secs("").is_err());
+        assert!(secs("0m").is_err());
+        assert!(secs("1h30").is_err());
+        assert!(secs("h").is_err());
+        assert!(secs("5d").is_err());
+    }
+
+    #[test]
+    fn durations_that_overflow_are_errors() {
+        let secs = |s| parse_duration(s).map(|d| d.as_secs());
+        assert_eq!(secs("307445734561825860"), Ok(307445734561825860 * 60));
+        assert!(secs("307445734561825861").is_err());
+        assert!(secs("5124095576030432h").is_err());
+        assert!(secs("18446744073709551615s1s").is_err());
+        assert_eq!(
+            secs("99999999999999999999m"),
+            Err("99999999999999999999m is too long".to_owned())
+        );
+    }
+
+    #[test]
+    fn date_ranges_go_forward() {
+        let range = "2026-10-12..2026-10-18".parse::<DateRange>().unwrap();
+        assert_eq!(range.0, NaiveDate::from_ymd_opt(2026, 10, 12).unwrap());
+        assert_eq!(range.1, NaiveDate::from_ymd_opt(2026, 10, 18).unwrap());
+        assert!("2026-10-12..2026-10-12".parse::<DateRange>().is_ok());
+        assert!("2026-10-18..2026-10-12".parse::<DateRange>().is_err());
+        assert!("2026-10-12".parse::<DateRange>().is_err());
+        assert!("2026-10-12..tomorrow".parse::<DateRange>().is_err());
+    }
+}
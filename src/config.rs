@@ -2,64 +2,215 @@
 #![allow(unused_variables)]
 
 pub mod alert_config;
+pub mod budget_config;
 pub mod date_config;
+pub mod diagnostics;
+pub mod dirs;
+pub mod idle_config;
+pub mod layers;
+pub mod profile_config;
 pub mod recorder_config;
+pub mod value_de;
 
 use alert_config::AlertConfig;
+use budget_config::Budget;
 use date_config::DateTimeConfig;
+use diagnostics::{Diagnostics, KeyPath, Schema};
+use idle_config::IdleConfig;
+use layers::{Layers, Sources};
+use profile_config::{Profile, Profiles, ScheduleBlock};
 use recorder_config::RecorderConfig;
 
+use std::collections::BTreeMap;
 use std::env;
-use std::fs::File;
 use std::io;
-use std::io::prelude::*;
+use std::path::Path;
 
 use serde::Deserialize;
 use thiserror::Error;
 
-const DEFAULT_SHARE: &str = "/.local/share/Septem/";
-const DEFAULT_CONFIG: &str = "/.config/Septem/septem.toml";
+const CONFIG_FILE: &str = "septem.toml";
 
 #[derive(Error, Debug)]
 pub enum ConfigError {
     #[error("Failed to retrieve env HOME:\n{0}")]
     EnvError(#[from] env::VarError),
 
-    #[error("Failed to find or read config file:\n{0}")]
-    FileIoError(#[from] io::Error),
+    #[error("Failed to find or read the config file {0}:\n{1}")]
+    ReadError(String, io::Error),
 
-    #[error("Toml-rs failed to parse the config file:\n{0}")]
-    TomlError(#[from] toml::de::Error),
+    #[error("Failed to create the directory {0}:\n{1}")]
+    DirError(String, io::Error),
+
+    #[error("Refusing to use the directory {0}:\n{1}")]
+    UnsafeDirError(String, io::Error),
+
+    #[error("The config file has problems:\n{0}")]
+    InvalidError(Diagnostics),
 }
 
+const SCHEMA: Schema = Schema::Table(&[
+    ("share_directory", Schema::Value),
+    ("recorder", recorder_config::SCHEMA),
+    ("date_and_time", date_config::SCHEMA),
+    ("alerts", alert_config::SCHEMA),
+    ("idle", idle_config::SCHEMA),
+    ("budgets", budget_config::SCHEMA),
+    ("watch_config", Schema::Value),
+    ("include", Schema::Value),
+    ("merge_lists", Schema::Value),
+    ("profiles", profile_config::SCHEMA),
+    ("schedule", profile_config::SCHEDULE_SCHEMA),
+]);
+
 #[derive(Deserialize, Debug)]
 pub struct Config {
     share_directory: Option<String>,
     recorder: RecorderConfig,
     date_and_time: Option<DateTimeConfig>,
     alerts: Option<AlertConfig>,
+    idle: Option<IdleConfig>,
+    #[serde(default)]
+    budgets: Vec<Budget>,
+    // Reload when the config file changes, SIGHUP always reloads
+    #[serde(default)]
+    watch_config: bool,
+    #[serde(default)]
+    schedule: Vec<ScheduleBlock>,
+    // Made from the profiles table merged over the top level
+    #[serde(skip)]
+    profiles: BTreeMap<String, Profile>,
+    // Kept to point at where problems found later are
+    #[serde(skip)]
+    sources: Sources,
 }
 
 impl Config {
-    // Temp default solution
+    // The given config file or the user's
+    pub fn path(c: Option<String>) -> Result<String, ConfigError> {
+        match c {
+            Some(s) => Ok(s),
+            None => {
+                let user = dirs::config_home()?.join(dirs::APP_DIR).join(CONFIG_FILE);
+                Ok(user.to_string_lossy().into_owned())
+            }
+        }
+    }
+
+    // The system wide files that exist, then the user's file, the fragments
+    // they include and last SEPTEM_ variables, each merged over the others.
+    // The user's file can be missing when there's a system wide one,
+    // unless it was given.
     pub fn new(c: Option<String>) -> Result<Config, ConfigError> {
-        let config_path = match c {
-            Some(s) => s,
-            None => env::var("HOME")? + DEFAULT_CONFIG,
+        let given = c.is_some();
+        let user = Config::path(c)?;
+        let mut layers = Layers::new(&SCHEMA);
+        for file in Layers::system_files() {
+            if file.is_file() {
+                layers.read(&file, 0)?;
+            }
+        }
+        if given || layers.sources.files().is_empty() || Path::new(&user).exists() {
+            layers.read(Path::new(&user), 0)?;
+        } else {
+            layers.sources.expect(Path::new(&user));
+        }
+        layers.read_env();
+
+        let mut problems = std::mem::take(&mut layers.problems);
+        let mut config = match value_de::from_value::<Config>(layers.value()) {
+            Ok(c) => Some(c),
+            Err(e) => {
+                // Syntax errors leave their file out, what's missing isn't news
+                if !layers.broken {
+                    problems.push(layers.sources.type_problem(e, &KeyPath::new()));
+                }
+                None
+            }
         };
 
-        let mut config_contents = String::new();
+        for (name, value) in layers.profiles() {
+            match value_de::from_value::<Profile>(value) {
+                Ok(p) => {
+                    if let Some(c) = config.as_mut() {
+                        c.profiles.insert(name, p);
+                    }
+                }
+                Err(e) if !layers.broken => {
+                    let prefix = KeyPath::new().key("profiles").key(&name);
+                    problems.push(layers.sources.type_problem(e, &prefix));
+                }
+                Err(_) => {}
+            }
+        }
+
+        match config {
+            Some(mut c) if problems.is_empty() => {
+                c.sources = layers.sources;
+                Ok(c)
+            }
+            _ => Err(ConfigError::InvalidError(Diagnostics::new(problems))),
+        }
+    }
+
+    // Points every problem found after parsing at its place in the files
+    pub fn diagnose(&self, problems: Vec<(KeyPath, String)>) -> ConfigError {
+        let problems = problems
+            .into_iter()
+            .map(|(path, message)| self.sources.problem(&path, message))
+            .collect();
+        ConfigError::InvalidError(Diagnostics::new(problems))
+    }
+
+    // Every file the config was read from, to watch for changes
+    pub fn files(&self) -> Vec<String> {
+        self.sources.files()
+    }
 
-        File::open(config_path).and_then(|mut f| f.read_to_string(&mut config_contents))?;
-        Ok(toml::from_str(config_contents.as_str())?)
+    // Globs of files that would be read if they were made,
+    // the includes and a user file that's missing
+    pub fn patterns(&self) -> Vec<String> {
+        self.sources.patterns()
     }
 
     pub fn share(&self) -> Result<String, ConfigError> {
-        let share = self.share_directory.to_owned();
-        match share {
-            Some(s) => Ok(s),
-            None => Ok(env::var("HOME")? + DEFAULT_SHARE),
+        let share = match &self.share_directory {
+            Some(s) => dirs::expand_tilde(s)?,
+            None => dirs::data_home()?.join(dirs::APP_DIR),
+        };
+        Ok(share.to_string_lossy().into_owned())
+    }
+
+    // Where state that isn't worth backing up is kept
+    pub fn state() -> Result<String, ConfigError> {
+        let state = dirs::state_home()?.join(dirs::APP_DIR);
+        Ok(state.to_string_lossy().into_owned())
+    }
+
+    // Overrides share_directory, ie from the command line
+    pub fn set_share(&mut self, share: String) {
+        self.share_directory = Some(share);
+    }
+
+    // Where sockets for other programs to talk to us are made,
+    // as long as no one else could have made it
+    pub fn runtime() -> Result<String, ConfigError> {
+        let runtime = dirs::runtime_dir();
+        let name = runtime.to_string_lossy().into_owned();
+        dirs::check_private(&runtime)
+            .map_err(|e| ConfigError::UnsafeDirError(name.to_owned(), e))?;
+        Ok(name)
+    }
+
+    // Makes the directories the daemon writes to on its first run
+    pub fn create_dirs(&self) -> Result<(), ConfigError> {
+        let runtime = dirs::runtime_dir().to_string_lossy().into_owned();
+        for dir in [self.share()?, Config::state()?, runtime] {
+            dirs::create_private(Path::new(&dir))
+                .map_err(|e| ConfigError::DirError(dir.to_owned(), e))?;
         }
+        Config::runtime()?;
+        Ok(())
     }
 
     pub fn recorder_config(&self) -> RecorderConfig {
@@ -73,4 +224,33 @@ impl Config {
     pub fn alert_config(&self) -> AlertConfig {
         self.alerts.to_owned().unwrap_or_default()
     }
+
+    pub fn idle_config(&self) -> IdleConfig {
+        self.idle.to_owned().unwrap_or_default()
+    }
+
+    pub fn budget_config(&self) -> Vec<Budget> {
+        self.budgets.to_owned()
+    }
+
+    pub fn watch_config(&self) -> bool {
+        self.watch_config
+    }
+
+    // The named profiles and the top level settings as the default one,
+    // every profile has the top level's shared recorder keys
+    pub fn profiles(&self) -> Profiles {
+        let recorder = self.recorder_config();
+        let named = self
+            .profiles
+            .iter()
+            .map(|(name, p)| {
+                let conf = p.recorder_config().with_shared(&recorder);
+                let profile = Profile::new(conf, p.alert_config(), p.date_config());
+                (name.to_owned(), profile)
+            })
+            .collect();
+        let default = Profile::new(recorder, self.alert_config(), self.date_config());
+        Profiles::new(default, named, self.schedule.to_owned())
+    }
 }
@@ -1,10 +1,20 @@
+use super::diagnostics::Schema;
+
 use serde::Deserialize;
 
 // Todo: Add Enum and alert type for config
 // It can be a pop up message or play audio
 // Rn, I will just make it println! a message
 
+pub const SCHEMA: Schema = Schema::Table(&[
+    ("delay", Schema::Value),
+    ("productive_time", Schema::Value),
+    ("unproductive_time", Schema::Value),
+    ("message", Schema::Value),
+]);
+
 #[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
 pub struct AlertConfig {
     delay: u64,
     productive_time: f64,
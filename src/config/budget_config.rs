@@ -0,0 +1,53 @@
+use super::diagnostics::Schema;
+
+use chrono::NaiveTime;
+use serde::Deserialize;
+
+pub const SCHEMA: Schema = Schema::Tables(&Schema::Table(&[
+    ("app", Schema::Value),
+    ("category", Schema::Value),
+    ("max", Schema::Value),
+    ("min", Schema::Value),
+    ("by", Schema::Value),
+]));
+
+// Daily limit or goal for an app or a category, in minutes
+#[derive(Clone, Deserialize, Debug)]
+pub struct Budget {
+    app: Option<String>,
+    category: Option<String>,
+    max: Option<f64>,
+    min: Option<f64>,
+    // Time of day the minimum should be met by
+    by: Option<NaiveTime>,
+}
+
+impl Budget {
+    pub fn app(&self) -> Option<&String> {
+        self.app.as_ref()
+    }
+
+    pub fn category(&self) -> Option<&String> {
+        self.category.as_ref()
+    }
+
+    // What the budget is for, the app or the category
+    pub fn name(&self) -> &str {
+        self.app
+            .as_deref()
+            .or(self.category.as_deref())
+            .unwrap_or_default()
+    }
+
+    pub fn max(&self) -> Option<f64> {
+        self.max
+    }
+
+    pub fn min(&self) -> Option<f64> {
+        self.min
+    }
+
+    pub fn by(&self) -> Option<NaiveTime> {
+        self.by
+    }
+}
@@ -1,3 +1,5 @@
+use super::diagnostics::Schema;
+
 use chrono::{Month, NaiveTime, Weekday};
 use serde::Deserialize;
 
@@ -46,7 +48,27 @@ impl Default for Hours {
     }
 }
 
+pub const SCHEMA: Schema = Schema::Table(&[
+    (
+        "disabled_days",
+        Schema::Tables(&Schema::Table(&[
+            ("month", Schema::Value),
+            ("week", Schema::Value),
+            ("day", Schema::Value),
+        ])),
+    ),
+    (
+        "start_hours",
+        Schema::Tables(&Schema::Table(&[
+            ("weekday", Schema::Value),
+            ("start", Schema::Value),
+            ("stop", Schema::Value),
+        ])),
+    ),
+]);
+
 #[derive(Clone, Deserialize, Debug, Default)]
+#[serde(default)]
 pub struct DateTimeConfig {
     disabled_days: Vec<Date>,
     start_hours: Vec<Hours>,
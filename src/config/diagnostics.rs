@@ -0,0 +1,446 @@
+use std::fmt;
+use std::ops::Range;
+
+use toml_edit::{ImDocument, Item, Table, TableLike, Value};
+
+// The keys each table can have, so typos aren't silently ignored
+pub enum Schema {
+    // Anything, what's inside isn't checked
+    Value,
+    Table(&'static [(&'static str, Schema)]),
+    // An array of tables with the same keys
+    Tables(&'static Schema),
+    // A table of any keys whose values have the same schema
+    Map(&'static Schema),
+    // A table schema with keys that can't be set here, and why
+    Except(&'static Schema, &'static [&'static str], &'static str),
+}
+
+impl Schema {
+    // Why the keys can't be set, if they can't
+    pub fn check(&self, keys: &[String]) -> Option<String> {
+        let mut schema = self;
+        for key in keys {
+            if let Schema::Except(s, fixed, why) = schema {
+                if fixed.contains(&key.as_str()) {
+                    return Some(format!("`{}` {}", key, why));
+                }
+                schema = s;
+            }
+            match schema {
+                Schema::Value => return None,
+                Schema::Tables(_) => return Some(format!("`{}` is inside a list", key)),
+                Schema::Map(s) => schema = s,
+                Schema::Table(known) => match known.iter().find(|(k, _)| k == key) {
+                    Some((_, s)) => schema = s,
+                    None => return Some(unknown_key(key, known)),
+                },
+                Schema::Except(..) => return None,
+            }
+        }
+        None
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Key(String),
+    Index(usize),
+}
+
+// Where a value is in the config, ie date_and_time.start_hours[2].stop
+#[derive(Clone, Debug, Default)]
+pub struct KeyPath(Vec<Segment>);
+
+impl KeyPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn key(&self, key: &str) -> KeyPath {
+        let mut path = self.clone();
+        path.0.push(Segment::Key(key.to_owned()));
+        path
+    }
+
+    pub fn index(&self, index: usize) -> KeyPath {
+        let mut path = self.clone();
+        path.0.push(Segment::Index(index));
+        path
+    }
+
+    // The path followed by the other one
+    pub fn join(&self, path: &KeyPath) -> KeyPath {
+        let mut joined = self.clone();
+        joined.0.extend(path.0.iter().cloned());
+        joined
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    // If the keys go through the whole path
+    pub fn is_prefix_of(&self, keys: &[String]) -> bool {
+        self.0.len() <= keys.len()
+            && self
+                .0
+                .iter()
+                .zip(keys)
+                .all(|(s, k)| matches!(s, Segment::Key(p) if p == k))
+    }
+
+    // If the path goes through the keys, with no index in between
+    pub fn starts_with(&self, keys: &[String]) -> bool {
+        keys.len() <= self.0.len()
+            && keys
+                .iter()
+                .zip(&self.0)
+                .all(|(k, s)| matches!(s, Segment::Key(p) if p == k))
+    }
+}
+
+impl fmt::Display for KeyPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Key(k) if i == 0 => write!(f, "{}", k)?,
+                Segment::Key(k) => write!(f, ".{}", k)?,
+                Segment::Index(n) => write!(f, "[{}]", n)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+// Checks that stop at their first error keep going with this
+pub fn first<E>(problems: Vec<(KeyPath, E)>) -> Result<(), E> {
+    match problems.into_iter().next() {
+        Some((_, e)) => Err(e),
+        None => Ok(()),
+    }
+}
+
+// A problem with the config and the file, or variable, it's in
+#[derive(Debug)]
+pub struct Problem {
+    file: String,
+    // Line and column, when known
+    position: Option<(usize, usize)>,
+    message: String,
+}
+
+impl Problem {
+    pub fn new(file: &str, message: String) -> Self {
+        Problem {
+            file: file.to_owned(),
+            position: None,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some((line, column)) => {
+                write!(f, "{}:{}:{}: {}", self.file, line, column, self.message)
+            }
+            None => write!(f, "{}: {}", self.file, self.message),
+        }
+    }
+}
+
+// Every problem found in the config
+#[derive(Debug)]
+pub struct Diagnostics(Vec<Problem>);
+
+impl Diagnostics {
+    pub fn new(problems: Vec<Problem>) -> Self {
+        Diagnostics(problems)
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lines: Vec<String> = self.0.iter().map(|p| p.to_string()).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+// Anything in the parsed file that can hold keys or be indexed
+#[derive(Clone, Copy)]
+enum Node<'a> {
+    Item(&'a Item),
+    Value(&'a Value),
+    Table(&'a Table),
+}
+
+impl<'a> Node<'a> {
+    fn table_like(self) -> Option<&'a dyn TableLike> {
+        match self {
+            Node::Item(i) => i.as_table_like(),
+            Node::Value(v) => v.as_inline_table().map(|t| t as &dyn TableLike),
+            Node::Table(t) => Some(t),
+        }
+    }
+
+    fn key(self, key: &str) -> Option<(Node<'a>, Option<Range<usize>>)> {
+        let (k, item) = self.table_like()?.get_key_value(key)?;
+        Some((Node::Item(item), k.span()))
+    }
+
+    fn index(self, index: usize) -> Option<(Node<'a>, Option<Range<usize>>)> {
+        match self {
+            Node::Item(Item::ArrayOfTables(a)) => a.get(index).map(|t| (Node::Table(t), t.span())),
+            Node::Item(Item::Value(Value::Array(a))) | Node::Value(Value::Array(a)) => {
+                a.get(index).map(|v| (Node::Value(v), v.span()))
+            }
+            _ => None,
+        }
+    }
+}
+
+// Edits needed to turn one key into the other,
+// swapping two letters counts as one
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+fn suggest(key: &str, keys: &[(&'static str, Schema)]) -> Option<&'static str> {
+    keys.iter()
+        .map(|(k, _)| (distance(key, k), *k))
+        .filter(|(d, _)| *d <= (key.len() / 3).max(1))
+        .min()
+        .map(|(_, k)| k)
+}
+
+fn unknown_key(key: &str, keys: &[(&'static str, Schema)]) -> String {
+    match suggest(key, keys) {
+        Some(s) => format!("unknown key `{}`, did you mean `{}`?", key, s),
+        None => format!("unknown key `{}`", key),
+    }
+}
+
+// The text of a config file, to point at where problems are
+pub struct Source<'a> {
+    file: &'a str,
+    text: &'a str,
+    doc: Result<ImDocument<&'a str>, toml_edit::TomlError>,
+}
+
+impl<'a> Source<'a> {
+    pub fn new(file: &'a str, text: &'a str) -> Self {
+        Source {
+            file,
+            text,
+            doc: ImDocument::parse(text),
+        }
+    }
+
+    pub fn parsed(&self) -> bool {
+        self.doc.is_ok()
+    }
+
+    fn problem_at(&self, offset: usize, message: String) -> Problem {
+        let before = &self.text[..offset.min(self.text.len())];
+        let line_start = before.rfind('\n').map_or(0, |n| n + 1);
+        Problem {
+            file: self.file.to_owned(),
+            position: Some((
+                before.matches('\n').count() + 1,
+                before[line_start..].chars().count() + 1,
+            )),
+            message,
+        }
+    }
+
+    // Line and column as toml-rs counts them, from 0
+    pub fn problem_at_line(&self, line: usize, column: usize, message: String) -> Problem {
+        Problem {
+            file: self.file.to_owned(),
+            position: Some((line + 1, column + 1)),
+            message,
+        }
+    }
+
+    // How much of the path is in the file and where the last of it is
+    pub fn locate(&self, path: &KeyPath) -> (usize, usize) {
+        let mut found = (0, 0);
+        if let Ok(doc) = &self.doc {
+            let mut node = Node::Table(doc.as_table());
+            for (depth, segment) in path.0.iter().enumerate() {
+                let next = match segment {
+                    Segment::Key(k) => node.key(k),
+                    Segment::Index(i) => node.index(*i),
+                };
+                match next {
+                    Some((n, span)) => {
+                        node = n;
+                        found.0 = depth + 1;
+                        if let Some(s) = span {
+                            found.1 = s.start;
+                        }
+                    }
+                    None => break,
+                }
+            }
+        }
+        found
+    }
+
+    // Points at the value, or as close to it as the file gets
+    pub fn problem(&self, path: &KeyPath, message: String) -> Problem {
+        let (_, offset) = self.locate(path);
+        self.problem_at(offset, format!("{}: {}", path, message))
+    }
+
+    // Syntax errors, or else every key the schema doesn't have
+    pub fn problems(&self, schema: &Schema) -> Vec<Problem> {
+        match &self.doc {
+            Ok(doc) => {
+                let mut problems = Vec::new();
+                self.unknown_keys(
+                    Node::Table(doc.as_table()),
+                    &KeyPath::new(),
+                    schema,
+                    &mut problems,
+                );
+                problems
+            }
+            Err(e) => {
+                let offset = e.span().map_or(0, |s| s.start);
+                let message: Vec<&str> = e.message().lines().collect();
+                vec![self.problem_at(offset, message.join(", "))]
+            }
+        }
+    }
+
+    fn unknown_keys(
+        &self,
+        node: Node,
+        path: &KeyPath,
+        schema: &Schema,
+        problems: &mut Vec<Problem>,
+    ) {
+        match schema {
+            Schema::Value => {}
+            Schema::Table(keys) => {
+                let table = match node.table_like() {
+                    Some(t) => t,
+                    None => return,
+                };
+                for (key, item) in table.iter() {
+                    match keys.iter().find(|(k, _)| *k == key) {
+                        Some((_, s)) => {
+                            self.unknown_keys(Node::Item(item), &path.key(key), s, problems)
+                        }
+                        None => {
+                            let offset = table
+                                .get_key_value(key)
+                                .and_then(|(k, _)| k.span())
+                                .map_or(0, |s| s.start);
+                            problems.push(self.problem_at(offset, unknown_key(key, keys)));
+                        }
+                    }
+                }
+            }
+            Schema::Map(s) => {
+                if let Some(table) = node.table_like() {
+                    for (key, item) in table.iter() {
+                        self.unknown_keys(Node::Item(item), &path.key(key), s, problems);
+                    }
+                }
+            }
+            Schema::Except(s, fixed, why) => {
+                if let Some(table) = node.table_like() {
+                    for (key, _) in table.iter().filter(|(k, _)| fixed.contains(k)) {
+                        let offset = table
+                            .get_key_value(key)
+                            .and_then(|(k, _)| k.span())
+                            .map_or(0, |s| s.start);
+                        problems.push(self.problem_at(offset, format!("`{}` {}", key, why)));
+                    }
+                }
+                self.unknown_keys(node, path, s, problems);
+            }
+            Schema::Tables(s) => {
+                let mut i = 0;
+                while let Some((n, _)) = node.index(i) {
+                    self.unknown_keys(n, &path.index(i), s, problems);
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEYS: &[(&str, Schema)] = &[
+        ("write_delay", Schema::Value),
+        ("productive", Schema::Value),
+        ("process_name", Schema::Value),
+        ("storage", Schema::Value),
+    ];
+
+    #[test]
+    fn distance_counts_edits_and_swaps() {
+        assert_eq!(distance("storage", "storage"), 0);
+        assert_eq!(distance("storge", "storage"), 1);
+        assert_eq!(distance("stroage", "storage"), 1);
+        assert_eq!(distance("storages", "storage"), 1);
+        assert_eq!(distance("", "abc"), 3);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggest_the_closest_key_if_its_close_enough() {
+        assert_eq!(suggest("write_dealy", KEYS), Some("write_delay"));
+        assert_eq!(suggest("prodcutive", KEYS), Some("productive"));
+        assert_eq!(suggest("strage", KEYS), Some("storage"));
+        assert_eq!(suggest("threshold", KEYS), None);
+        assert_eq!(suggest("x", KEYS), None);
+    }
+
+    #[test]
+    fn unknown_keys_come_with_a_suggestion() {
+        let schema = Schema::Table(&[("recorder", Schema::Table(KEYS))]);
+        let keys = |k: &[&str]| k.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        assert_eq!(schema.check(&keys(&["recorder", "storage"])), None);
+        assert_eq!(
+            schema.check(&keys(&["recorder", "storag"])),
+            Some("unknown key `storag`, did you mean `storage`?".to_owned())
+        );
+        assert_eq!(
+            schema.check(&keys(&["idle"])),
+            Some("unknown key `idle`".to_owned())
+        );
+    }
+}
@@ -0,0 +1,133 @@
+use std::env;
+use std::fs::DirBuilder;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+pub const APP_DIR: &str = "Septem";
+// Where the daemon listens for the native messaging host, in the runtime directory
+pub const BROWSER_SOCKET: &str = "septem-browser.sock";
+const DEFAULT_CONFIG_DIRS: &str = "/etc/xdg";
+const DEFAULT_RUNTIME: &str = "/tmp";
+
+fn home() -> Result<PathBuf, env::VarError> {
+    env::var("HOME").map(PathBuf::from)
+}
+
+// The spec says to ignore relative paths as well as empty ones
+fn xdg_var(name: &str) -> Option<PathBuf> {
+    env::var_os(name)
+        .map(PathBuf::from)
+        .filter(|p| p.is_absolute())
+}
+
+fn xdg_home(name: &str, default: &str) -> Result<PathBuf, env::VarError> {
+    match xdg_var(name) {
+        Some(p) => Ok(p),
+        None => Ok(home()?.join(default)),
+    }
+}
+
+pub fn config_home() -> Result<PathBuf, env::VarError> {
+    xdg_home("XDG_CONFIG_HOME", ".config")
+}
+
+pub fn data_home() -> Result<PathBuf, env::VarError> {
+    xdg_home("XDG_DATA_HOME", ".local/share")
+}
+
+pub fn state_home() -> Result<PathBuf, env::VarError> {
+    xdg_home("XDG_STATE_HOME", ".local/state")
+}
+
+// System wide config directories, most important first
+pub fn config_dirs() -> Vec<PathBuf> {
+    let dirs = env::var("XDG_CONFIG_DIRS").unwrap_or_default();
+    let dirs: Vec<PathBuf> = env::split_paths(&dirs)
+        .filter(|p| p.is_absolute())
+        .collect();
+    if dirs.is_empty() {
+        vec![PathBuf::from(DEFAULT_CONFIG_DIRS)]
+    } else {
+        dirs
+    }
+}
+
+// Without XDG_RUNTIME_DIR sockets go in a directory of our own in /tmp.
+// Anyone can make it there first, so check_private has to pass before it's used.
+pub fn runtime_dir() -> PathBuf {
+    match xdg_var("XDG_RUNTIME_DIR") {
+        Some(p) => p,
+        None => {
+            let uid = unsafe { libc::getuid() };
+            Path::new(DEFAULT_RUNTIME).join(format!("septem-{}", uid))
+        }
+    }
+}
+
+// ~ and ~/ at the start of a path are the home directory
+pub fn expand_tilde(path: &str) -> Result<PathBuf, env::VarError> {
+    match path.strip_prefix('~') {
+        Some("") => home(),
+        Some(rest) if rest.starts_with('/') => Ok(home()?.join(&rest[1..])),
+        _ => Ok(PathBuf::from(path)),
+    }
+}
+
+// Makes the directory and any missing parents readable only by us,
+// directories that already exist are left as they are
+pub fn create_private(path: &Path) -> io::Result<()> {
+    DirBuilder::new().recursive(true).mode(0o700).create(path)
+}
+
+// Fails unless the path is a directory, not a link to one,
+// that we own and no one else can use
+pub fn check_private(path: &Path) -> io::Result<()> {
+    let meta = path.symlink_metadata()?;
+    let uid = unsafe { libc::getuid() };
+    if !meta.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "it isn't a directory",
+        ));
+    }
+    if meta.uid() != uid {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("it is owned by uid {}, not {}", meta.uid(), uid),
+        ));
+    }
+    if meta.mode() & 0o777 != 0o700 {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("its mode is {:o}, not 700", meta.mode() & 0o777),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{remove_dir_all, set_permissions, Permissions};
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    #[test]
+    fn only_our_own_private_directory_passes() {
+        let dir = env::temp_dir().join(format!("septem-private-{}", std::process::id()));
+        let _ = remove_dir_all(&dir);
+        let private = dir.join("private");
+        create_private(&private).unwrap();
+        assert!(check_private(&private).is_ok());
+
+        set_permissions(&private, Permissions::from_mode(0o755)).unwrap();
+        assert!(check_private(&private).is_err());
+
+        set_permissions(&private, Permissions::from_mode(0o700)).unwrap();
+        let link = dir.join("link");
+        symlink(&private, &link).unwrap();
+        assert!(check_private(&link).is_err());
+        remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,32 @@
+use super::diagnostics::Schema;
+
+use serde::Deserialize;
+
+pub const SCHEMA: Schema = Schema::Table(&[("threshold", Schema::Value), ("delay", Schema::Value)]);
+
+// Keys left out, ie by a file layered over another, are the defaults
+#[derive(Clone, Deserialize, Debug)]
+#[serde(default)]
+pub struct IdleConfig {
+    threshold: u64,
+    delay: u64,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            threshold: 300, // Idle after 5 minutes without input
+            delay: 1000,    // Checks the idle counter every second
+        }
+    }
+}
+
+impl IdleConfig {
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    pub fn delay(&self) -> u64 {
+        self.delay
+    }
+}
@@ -0,0 +1,352 @@
+use super::diagnostics::{KeyPath, Problem, Schema, Source};
+use super::profile_config::PROFILE_KEYS;
+use super::value_de::TypeError;
+use super::{dirs, ConfigError};
+
+use std::env;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+
+use toml::value::{Table, Value};
+
+// Shared defaults every user's config is merged on top of
+pub const SYSTEM_CONFIG: &str = "/etc/septem/septem.toml";
+// ie SEPTEM_RECORDER__WRITE_DELAY=10 sets recorder.write_delay
+const ENV_PREFIX: &str = "SEPTEM_";
+const ENV_SEPARATOR: &str = "__";
+// Includes nested deeper than this are taken as a loop
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+// A file the config was read from
+#[derive(Debug)]
+struct Layer {
+    file: String,
+    text: String,
+}
+
+// Where every part of the merged config came from,
+// to point at the right file when something is wrong
+#[derive(Debug, Default)]
+pub struct Sources {
+    // Least important first
+    layers: Vec<Layer>,
+    // Variables and the keys they set
+    env: Vec<(String, Vec<String>)>,
+    // Globs of files that are read once they exist, ie conf.d/*.toml
+    patterns: Vec<String>,
+}
+
+impl Sources {
+    pub fn files(&self) -> Vec<String> {
+        self.layers.iter().map(|l| l.file.to_owned()).collect()
+    }
+
+    pub fn patterns(&self) -> Vec<String> {
+        self.patterns.to_owned()
+    }
+
+    // A file that wasn't there to read but would be
+    pub fn expect(&mut self, file: &Path) {
+        let name = file.to_string_lossy();
+        self.patterns.push(glob::Pattern::escape(&name));
+    }
+
+    // The most important file with the most of the path in it,
+    // unless an environment variable set it
+    pub fn problem(&self, path: &KeyPath, message: String) -> Problem {
+        let mut best: Option<(usize, Source)> = None;
+        for layer in &self.layers {
+            let source = Source::new(&layer.file, &layer.text);
+            let (depth, _) = source.locate(path);
+            if best.as_ref().is_none_or(|(d, _)| depth >= *d) {
+                best = Some((depth, source));
+            }
+        }
+
+        // A table only a variable made is blamed on it too
+        let in_file = best.as_ref().is_some_and(|(d, _)| *d == path.len());
+        let set =
+            |keys: &Vec<String>| path.starts_with(keys) || (!in_file && path.is_prefix_of(keys));
+        if let Some((var, _)) = self.env.iter().rev().find(|(_, k)| set(k)) {
+            return Problem::new(var, format!("{}: {}", path, message));
+        }
+
+        match best {
+            Some((_, source)) => source.problem(path, message),
+            None => Problem::new("config", format!("{}: {}", path, message)),
+        }
+    }
+
+    // A value of the merged config that doesn't fit, in the file that set it
+    pub fn type_problem(&self, e: TypeError, prefix: &KeyPath) -> Problem {
+        self.problem(&e.path(prefix), e.message().to_owned())
+    }
+}
+
+// Builds the config up from every file and variable that sets part of it
+pub struct Layers {
+    schema: &'static Schema,
+    value: Table,
+    // Lists that are added to instead of replaced, ie "recorder.productive"
+    merge_lists: Vec<String>,
+    pub sources: Sources,
+    pub problems: Vec<Problem>,
+    // A file couldn't be parsed, so what it sets is missing
+    pub broken: bool,
+}
+
+impl Layers {
+    pub fn new(schema: &'static Schema) -> Self {
+        Layers {
+            schema,
+            value: Table::new(),
+            merge_lists: Vec::new(),
+            sources: Sources::default(),
+            problems: Vec::new(),
+            broken: false,
+        }
+    }
+
+    // System wide files, least important first
+    pub fn system_files() -> Vec<PathBuf> {
+        let mut files = vec![PathBuf::from(SYSTEM_CONFIG)];
+        for dir in dirs::config_dirs().into_iter().rev() {
+            files.push(dir.join(dirs::APP_DIR).join(super::CONFIG_FILE));
+        }
+        files
+    }
+
+    // Merges the file and the fragments it includes on top of what's read so far
+    pub fn read(&mut self, file: &Path, depth: usize) -> Result<(), ConfigError> {
+        let name = file.to_string_lossy().into_owned();
+        let mut text = String::new();
+        File::open(file)
+            .and_then(|mut f| f.read_to_string(&mut text))
+            .map_err(|e| ConfigError::ReadError(name.to_owned(), e))?;
+
+        let source = Source::new(&name, &text);
+        self.problems.extend(source.problems(self.schema));
+        self.broken |= !source.parsed();
+        let mut table = match source.parsed() {
+            true => toml::from_str::<Table>(&text).unwrap_or_default(),
+            false => Table::new(),
+        };
+
+        let mut includes = Vec::new();
+        if let Some(value) = table.remove("include") {
+            match strings(value) {
+                Some(paths) if depth < MAX_INCLUDE_DEPTH => includes = paths,
+                Some(_) => {
+                    let message = "includes are nested too deep, is there a loop?".to_owned();
+                    self.problems
+                        .push(source.problem(&KeyPath::new().key("include"), message));
+                }
+                None => {
+                    let message = "must be a list of paths".to_owned();
+                    self.problems
+                        .push(source.problem(&KeyPath::new().key("include"), message));
+                }
+            }
+        }
+        if let Some(value) = table.remove("merge_lists") {
+            match strings(value) {
+                Some(lists) => self.merge_lists.extend(lists),
+                None => {
+                    let message = "must be a list of keys".to_owned();
+                    let path = KeyPath::new().key("merge_lists");
+                    self.problems.push(source.problem(&path, message));
+                }
+            }
+        }
+
+        merge(&mut self.value, table, &self.merge_lists, "");
+        self.sources.layers.push(Layer {
+            file: name.to_owned(),
+            text: text.to_owned(),
+        });
+
+        // Relative to the file including them, and can be globs like conf.d/*.toml
+        let dir = file.parent().unwrap_or_else(|| Path::new("."));
+        for include in includes {
+            let pattern = dir.join(dirs::expand_tilde(&include)?);
+            let pattern = pattern.to_string_lossy();
+            self.sources.patterns.push(pattern.to_string());
+            let mut found: Vec<PathBuf> = glob::glob(&pattern)
+                .map(|paths| paths.filter_map(Result::ok).collect())
+                .unwrap_or_default();
+            found.sort();
+            if found.is_empty() && !include.contains(['*', '?', '[']) {
+                found.push(PathBuf::from(pattern.as_ref()));
+            }
+            for f in found {
+                self.read(&f, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Every SEPTEM_ variable, on top of all the files
+    pub fn read_env(&mut self) {
+        self.read_vars(env::vars().collect());
+    }
+
+    // Variables that don't set a key might be meant for something else,
+    // so they're only warned about
+    fn read_vars(&mut self, mut vars: Vec<(String, String)>) {
+        vars.retain(|(k, _)| k.starts_with(ENV_PREFIX));
+        vars.sort();
+
+        for (var, raw) in vars {
+            let keys: Vec<String> = var[ENV_PREFIX.len()..]
+                .split(ENV_SEPARATOR)
+                .map(|k| k.to_lowercase())
+                .collect();
+            if let Some(message) = self.schema.check(&keys) {
+                eprintln!("Ignoring {}: {}", var, message);
+                continue;
+            }
+
+            // Anything that isn't a TOML value is taken as a string
+            let value = toml::from_str::<Table>(&format!("v = {}", raw))
+                .ok()
+                .and_then(|mut t| t.remove("v"))
+                .unwrap_or(Value::String(raw));
+            let table = keys.iter().rev().fold(value, |v, k| {
+                let mut t = Table::new();
+                t.insert(k.to_owned(), v);
+                Value::Table(t)
+            });
+            if let Value::Table(t) = table {
+                merge(&mut self.value, t, &self.merge_lists, "");
+            }
+            self.sources.env.push((var, keys));
+        }
+    }
+
+    pub fn value(&self) -> Value {
+        Value::Table(self.value.to_owned())
+    }
+
+    // Each profile's tables merged over the top level ones
+    pub fn profiles(&self) -> Vec<(String, Value)> {
+        let named = match self.value.get("profiles") {
+            Some(Value::Table(t)) => t,
+            _ => return Vec::new(),
+        };
+
+        named
+            .iter()
+            .map(|(name, value)| {
+                let mut base = Table::new();
+                for key in PROFILE_KEYS {
+                    if let Some(v) = self.value.get(*key) {
+                        base.insert(key.to_string(), v.to_owned());
+                    }
+                }
+                let value = match value {
+                    Value::Table(t) => {
+                        merge(&mut base, t.to_owned(), &self.merge_lists, "");
+                        Value::Table(base)
+                    }
+                    // Left as is so it's reported as the wrong type
+                    v => v.to_owned(),
+                };
+                (name.to_owned(), value)
+            })
+            .collect()
+    }
+}
+
+fn strings(value: Value) -> Option<Vec<String>> {
+    match value {
+        Value::Array(a) => a
+            .into_iter()
+            .map(|v| v.as_str().map(|s| s.to_owned()))
+            .collect(),
+        _ => None,
+    }
+}
+
+// Tables are merged key by key, anything else replaces what was there
+// except for lists in merge_lists which are added to
+fn merge(base: &mut Table, top: Table, merge_lists: &[String], path: &str) {
+    for (key, value) in top {
+        let path = if path.is_empty() {
+            key.to_owned()
+        } else {
+            format!("{}.{}", path, key)
+        };
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(b)), Value::Table(t)) => merge(b, t, merge_lists, &path),
+            (Some(Value::Array(b)), Value::Array(t)) if merge_lists.contains(&path) => b.extend(t),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(text: &str) -> Table {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn tables_merge_key_by_key() {
+        let mut base = table("[recorder]\nwrite_delay = 20\nproductive = [\"code\"]\n");
+        let top = table("[recorder]\nproductive = [\"nvim\"]\n[idle]\nthreshold = 60\n");
+        merge(&mut base, top, &[], "");
+        assert_eq!(
+            base,
+            table(
+                "[recorder]\nwrite_delay = 20\nproductive = [\"nvim\"]\n[idle]\nthreshold = 60\n"
+            )
+        );
+    }
+
+    #[test]
+    fn merge_lists_are_added_to() {
+        let mut base = table("[recorder]\nproductive = [\"code\"]\nterminals = [\"xterm\"]\n");
+        let top = table("[recorder]\nproductive = [\"nvim\"]\nterminals = [\"alacritty\"]\n");
+        merge(&mut base, top, &["recorder.productive".to_owned()], "");
+        assert_eq!(
+            base,
+            table("[recorder]\nproductive = [\"code\", \"nvim\"]\nterminals = [\"alacritty\"]\n")
+        );
+    }
+
+    #[test]
+    fn variables_set_keys_and_unknown_ones_are_ignored() {
+        let mut layers = Layers::new(&crate::config::SCHEMA);
+        let var = |k: &str, v: &str| (k.to_owned(), v.to_owned());
+        layers.read_vars(vec![
+            var("SEPTEM_RECORDER__WRITE_DELAY", "10"),
+            var("SEPTEM_RECORDER__STORAGE", "sqlite"),
+            var("SEPTEM_RECORDER__PRODUCTIVE", "[\"nvim\"]"),
+            var("SEPTEM_RECORDER__WRTIE_DELAY", "30"),
+            var("SEPTEM_PID_FILE", "/tmp/septem.pid"),
+            var("HOME", "/home/septem"),
+        ]);
+
+        assert!(layers.problems.is_empty());
+        assert_eq!(
+            layers.value(),
+            Value::Table(table(
+                "[recorder]\nwrite_delay = 10\nstorage = \"sqlite\"\nproductive = [\"nvim\"]\n"
+            ))
+        );
+        assert_eq!(layers.sources.env.len(), 3);
+    }
+
+    #[test]
+    fn anything_else_is_replaced() {
+        let mut base = table("[recorder]\nproductive = [\"code\"]\n");
+        let top = table("recorder = 1\n");
+        merge(&mut base, top, &["recorder.productive".to_owned()], "");
+        assert_eq!(base, table("recorder = 1\n"));
+    }
+}
@@ -0,0 +1,284 @@
+use super::alert_config::{self, AlertConfig};
+use super::date_config::{self, DateTimeConfig};
+use super::diagnostics::Schema;
+use super::recorder_config::{self, RecorderConfig};
+
+use std::collections::BTreeMap;
+
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use serde::Deserialize;
+
+// The top level settings, used outside every scheduled block
+pub const DEFAULT_PROFILE: &str = "default";
+
+// The tables a profile can change
+pub const PROFILE_KEYS: &[&str] = &["recorder", "alerts", "date_and_time"];
+
+pub const SCHEMA: Schema = Schema::Map(&Schema::Table(&[
+    ("recorder", recorder_config::PROFILE_SCHEMA),
+    ("alerts", alert_config::SCHEMA),
+    ("date_and_time", date_config::SCHEMA),
+]));
+
+pub const SCHEDULE_SCHEMA: Schema = Schema::Tables(&Schema::Table(&[
+    ("profile", Schema::Value),
+    ("weekdays", Schema::Value),
+    ("start", Schema::Value),
+    ("stop", Schema::Value),
+]));
+
+// Settings used instead of the top level ones while the profile is active,
+// anything it doesn't set is taken from the top level
+#[derive(Clone, Deserialize, Debug)]
+pub struct Profile {
+    recorder: RecorderConfig,
+    #[serde(default)]
+    alerts: AlertConfig,
+    #[serde(default)]
+    date_and_time: DateTimeConfig,
+}
+
+impl Profile {
+    pub fn new(recorder: RecorderConfig, alerts: AlertConfig, date: DateTimeConfig) -> Self {
+        Profile {
+            recorder,
+            alerts,
+            date_and_time: date,
+        }
+    }
+
+    pub fn recorder_config(&self) -> RecorderConfig {
+        self.recorder.to_owned()
+    }
+
+    pub fn alert_config(&self) -> AlertConfig {
+        self.alerts.to_owned()
+    }
+
+    pub fn date_config(&self) -> DateTimeConfig {
+        self.date_and_time.to_owned()
+    }
+}
+
+// A time the profile is switched to by itself, the first block
+// the time is in wins
+#[derive(Clone, Deserialize, Debug)]
+pub struct ScheduleBlock {
+    profile: String,
+    #[serde(default)]
+    weekdays: Vec<Weekday>,
+    start: Option<NaiveTime>,
+    stop: Option<NaiveTime>,
+}
+
+impl ScheduleBlock {
+    pub fn profile(&self) -> &String {
+        &self.profile
+    }
+
+    // Every day when empty
+    pub fn weekdays(&self) -> &Vec<Weekday> {
+        &self.weekdays
+    }
+
+    // Midnight when missing
+    pub fn start(&self) -> NaiveTime {
+        self.start.unwrap_or(NaiveTime::MIN)
+    }
+
+    // The end of the day when missing
+    pub fn stop(&self) -> Option<NaiveTime> {
+        self.stop
+    }
+}
+
+// Every profile and when they're used
+#[derive(Clone, Debug)]
+pub struct Profiles {
+    default: Profile,
+    named: BTreeMap<String, Profile>,
+    schedule: Vec<ScheduleBlock>,
+}
+
+impl Profiles {
+    pub fn new(
+        default: Profile,
+        named: BTreeMap<String, Profile>,
+        schedule: Vec<ScheduleBlock>,
+    ) -> Self {
+        Profiles {
+            default,
+            named,
+            schedule,
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        if name == DEFAULT_PROFILE {
+            Some(&self.default)
+        } else {
+            self.named.get(name)
+        }
+    }
+
+    // The profiles from the profiles table, without the default one
+    pub fn named(&self) -> &BTreeMap<String, Profile> {
+        &self.named
+    }
+
+    pub fn schedule(&self) -> &Vec<ScheduleBlock> {
+        &self.schedule
+    }
+
+    // The profile of the first block the time is in
+    pub fn scheduled(&self, now: NaiveDateTime) -> &str {
+        let time = now.time();
+        self.schedule
+            .iter()
+            .find(|b| {
+                (b.weekdays.is_empty() || b.weekdays.contains(&now.weekday()))
+                    && time >= b.start()
+                    && b.stop.is_none_or(|s| time < s)
+            })
+            .map_or(DEFAULT_PROFILE, |b| b.profile.as_str())
+    }
+
+    // The next time a block starts or stops, at the latest midnight
+    // when the weekday changes
+    pub fn next_boundary(&self, now: NaiveDateTime) -> NaiveDateTime {
+        let today = now.date();
+        let midnight = today.and_time(NaiveTime::MIN) + Duration::days(1);
+        self.schedule
+            .iter()
+            .flat_map(|b| [Some(b.start()), b.stop])
+            .flatten()
+            .map(|t| today.and_time(t))
+            .filter(|t| *t > now)
+            .fold(midnight, NaiveDateTime::min)
+    }
+
+    // The chosen profile if there still is one, or else the scheduled one
+    pub fn active(&self, chosen: Option<&str>, now: NaiveDateTime) -> (String, Profile) {
+        let name = match chosen {
+            Some(c) if self.get(c).is_some() => c,
+            _ => self.scheduled(now),
+        };
+        match self.get(name) {
+            Some(p) => (name.to_owned(), p.to_owned()),
+            None => (DEFAULT_PROFILE.to_owned(), self.default.to_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::recorder_config::{ProcessName, StorageKind};
+
+    fn at(time: &str) -> NaiveDateTime {
+        time.parse().unwrap()
+    }
+
+    fn profile(recorder: &str) -> Profile {
+        Profile::new(
+            toml::from_str(recorder).unwrap(),
+            AlertConfig::default(),
+            DateTimeConfig::default(),
+        )
+    }
+
+    fn profiles() -> Profiles {
+        let mut named = BTreeMap::new();
+        named.insert("work".to_owned(), profile("write_delay = 20"));
+        named.insert("evening".to_owned(), profile("write_delay = 20"));
+        let schedule: Vec<ScheduleBlock> = toml::from_str::<BTreeMap<String, _>>(
+            r#"
+            blocks = [
+                {profile = "work", weekdays = ["Mon", "Tue", "Wed", "Thu", "Fri"], start = "09:00:00", stop = "17:00:00"},
+                {profile = "evening", start = "20:00:00"},
+                {profile = "removed", start = "06:00:00", stop = "07:00:00"},
+            ]
+            "#,
+        )
+        .unwrap()
+        .remove("blocks")
+        .unwrap();
+        Profiles::new(profile("write_delay = 20"), named, schedule)
+    }
+
+    #[test]
+    fn first_block_the_time_is_in_is_scheduled() {
+        let profiles = profiles();
+        // 2026-10-19 is a Monday
+        assert_eq!(profiles.scheduled(at("2026-10-19T09:00:00")), "work");
+        assert_eq!(
+            profiles.scheduled(at("2026-10-19T17:00:00")),
+            DEFAULT_PROFILE
+        );
+        assert_eq!(
+            profiles.scheduled(at("2026-10-18T12:00:00")),
+            DEFAULT_PROFILE
+        );
+        assert_eq!(profiles.scheduled(at("2026-10-18T21:00:00")), "evening");
+    }
+
+    #[test]
+    fn chosen_profile_wins_until_it_is_removed() {
+        let profiles = profiles();
+        let monday = at("2026-10-19T10:00:00");
+        assert_eq!(profiles.active(Some("evening"), monday).0, "evening");
+        assert_eq!(profiles.active(Some("default"), monday).0, DEFAULT_PROFILE);
+        assert_eq!(profiles.active(Some("removed"), monday).0, "work");
+        assert_eq!(profiles.active(None, monday).0, "work");
+        // A block for a profile that's gone falls back to the default
+        assert_eq!(
+            profiles.active(None, at("2026-10-19T06:30:00")).0,
+            DEFAULT_PROFILE
+        );
+    }
+
+    #[test]
+    fn next_boundary_is_the_next_start_or_stop_or_midnight() {
+        let profiles = profiles();
+        let next = |now| profiles.next_boundary(at(now));
+        assert_eq!(next("2026-10-19T05:00:00"), at("2026-10-19T06:00:00"));
+        assert_eq!(next("2026-10-19T09:00:00"), at("2026-10-19T17:00:00"));
+        assert_eq!(next("2026-10-19T18:00:00"), at("2026-10-19T20:00:00"));
+        assert_eq!(next("2026-10-19T21:00:00"), at("2026-10-20T00:00:00"));
+    }
+
+    #[test]
+    fn profiles_can_not_set_the_shared_keys() {
+        let keys = |k: &[&str]| k.iter().map(|k| k.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            SCHEMA.check(&keys(&["work", "recorder", "storage"])),
+            Some(
+                "`storage` is shared by every profile and can only be set at the top level"
+                    .to_owned()
+            )
+        );
+        assert_eq!(
+            SCHEMA.check(&keys(&["work", "recorder", "productive"])),
+            None
+        );
+        assert!(SCHEMA
+            .check(&keys(&["work", "recorder", "wirte_delay"]))
+            .is_some());
+    }
+
+    #[test]
+    fn shared_keys_come_from_the_top_level() {
+        let top: RecorderConfig = toml::from_str(
+            "write_delay = 5\nprocess_name = \"comm\"\nstorage = \"sqlite\"\nproductive = [\"code\"]",
+        )
+        .unwrap();
+        let work: RecorderConfig =
+            toml::from_str("write_delay = 60\nproductive = [\"nvim\"]").unwrap();
+
+        let work = work.with_shared(&top);
+        assert_eq!(work.write_delay(), 5);
+        assert_eq!(work.process_name(), ProcessName::Comm);
+        assert_eq!(work.storage(), StorageKind::Sqlite);
+        assert_eq!(work.productive(), &vec!["nvim".to_owned()]);
+    }
+}
@@ -1,17 +1,387 @@
+use super::diagnostics::Schema;
+
+use chrono::{NaiveTime, Weekday};
 use serde::Deserialize;
 
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageKind {
+    #[default]
+    Csv,
+    Sqlite,
+}
+
+// What names the time of a window
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Identity {
+    #[default]
+    Process,
+    Class,
+    Both,
+}
+
+// How the name of a process is read on Linux
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProcessName {
+    // /proc/<pid>/comm
+    Comm,
+    // Basename of /proc/<pid>/exe
+    Exe,
+    // Basename of the first argument in /proc/<pid>/cmdline
+    #[default]
+    Argv0,
+    // Every argument in /proc/<pid>/cmdline separated by spaces
+    Cmdline,
+}
+
+impl ProcessName {
+    // As it is written in the config
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProcessName::Comm => "comm",
+            ProcessName::Exe => "exe",
+            ProcessName::Argv0 => "argv0",
+            ProcessName::Cmdline => "cmdline",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ProcessName> {
+        [
+            ProcessName::Comm,
+            ProcessName::Exe,
+            ProcessName::Argv0,
+            ProcessName::Cmdline,
+        ]
+        .iter()
+        .copied()
+        .find(|p| p.name() == name)
+    }
+}
+
+// Named group of applications that share a productivity weight
+// from -1.0 (a waste of time) to 1.0 (productive)
+#[derive(Clone, Deserialize, Debug)]
+pub struct Category {
+    name: String,
+    weight: f64,
+    #[serde(default)]
+    apps: Vec<String>,
+    #[serde(default)]
+    titles: Vec<String>,
+}
+
+impl Category {
+    pub fn name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    pub fn apps(&self) -> &Vec<String> {
+        &self.apps
+    }
+
+    pub fn titles(&self) -> &Vec<String> {
+        &self.titles
+    }
+
+    fn matches(&self, names: &[&str], title: &str) -> bool {
+        self.apps.iter().any(|a| names.contains(&a.as_str()))
+            || self.titles.iter().any(|t| title.contains(t.as_str()))
+    }
+}
+
+// Extra productive apps for one day of the week,
+// added on top of the default productive lists
+#[derive(Clone, Deserialize, Debug)]
+pub struct WeekdayProductive {
+    weekday: Weekday,
+    #[serde(default)]
+    productive: Vec<String>,
+    #[serde(default)]
+    productive_titles: Vec<String>,
+}
+
+impl WeekdayProductive {
+    pub fn weekday(&self) -> Weekday {
+        self.weekday
+    }
+
+    pub fn productive(&self) -> &Vec<String> {
+        &self.productive
+    }
+
+    pub fn productive_titles(&self) -> &Vec<String> {
+        &self.productive_titles
+    }
+}
+
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchKind {
+    #[default]
+    Exact,
+    Glob,
+    Regex,
+}
+
+// What part of a window a rule's pattern is matched against
+#[derive(Clone, Copy, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleField {
+    // Process, terminal job, browser domain or the recorded name
+    #[default]
+    Process,
+    // WM_CLASS class or instance
+    Class,
+    Title,
+}
+
+// Puts matching windows in a category, the first rule to match wins.
+// A rule with weekdays or hours only applies during them.
+#[derive(Clone, Deserialize, Debug)]
+pub struct Rule {
+    #[serde(rename = "match", default)]
+    kind: MatchKind,
+    #[serde(default)]
+    field: RuleField,
+    pattern: String,
+    category: String,
+    // Overrides the category's weight
+    weight: Option<f64>,
+    #[serde(default)]
+    weekdays: Vec<Weekday>,
+    start: Option<NaiveTime>,
+    stop: Option<NaiveTime>,
+}
+
+impl Rule {
+    pub fn kind(&self) -> MatchKind {
+        self.kind
+    }
+
+    pub fn field(&self) -> RuleField {
+        self.field
+    }
+
+    pub fn pattern(&self) -> &String {
+        &self.pattern
+    }
+
+    pub fn category(&self) -> &String {
+        &self.category
+    }
+
+    pub fn weight(&self) -> Option<f64> {
+        self.weight
+    }
+
+    // Every day when empty
+    pub fn weekdays(&self) -> &Vec<Weekday> {
+        &self.weekdays
+    }
+
+    // Midnight when missing
+    pub fn start(&self) -> NaiveTime {
+        self.start.unwrap_or(NaiveTime::MIN)
+    }
+
+    // The end of the day when missing
+    pub fn stop(&self) -> Option<NaiveTime> {
+        self.stop
+    }
+}
+
+pub const PRODUCTIVE: &str = "productive";
+pub const UNCATEGORIZED: &str = "uncategorized";
+
+fn default_weight() -> f64 {
+    -1.0
+}
+
+pub const SCHEMA: Schema = Schema::Table(&[
+    ("write_delay", Schema::Value),
+    ("productive", Schema::Value),
+    ("productive_titles", Schema::Value),
+    (
+        "weekdays",
+        Schema::Tables(&Schema::Table(&[
+            ("weekday", Schema::Value),
+            ("productive", Schema::Value),
+            ("productive_titles", Schema::Value),
+        ])),
+    ),
+    (
+        "rules",
+        Schema::Tables(&Schema::Table(&[
+            ("match", Schema::Value),
+            ("field", Schema::Value),
+            ("pattern", Schema::Value),
+            ("category", Schema::Value),
+            ("weight", Schema::Value),
+            ("weekdays", Schema::Value),
+            ("start", Schema::Value),
+            ("stop", Schema::Value),
+        ])),
+    ),
+    (
+        "categories",
+        Schema::Tables(&Schema::Table(&[
+            ("name", Schema::Value),
+            ("weight", Schema::Value),
+            ("apps", Schema::Value),
+            ("titles", Schema::Value),
+        ])),
+    ),
+    ("default_weight", Schema::Value),
+    ("identity", Schema::Value),
+    ("process_name", Schema::Value),
+    ("terminals", Schema::Value),
+    ("browsers", Schema::Value),
+    ("storage", Schema::Value),
+]);
+
+// Keys every profile shares, they're read once on start up
+pub const SHARED_KEYS: &[&str] = &["write_delay", "identity", "process_name", "storage"];
+
+pub const PROFILE_SCHEMA: Schema = Schema::Except(
+    &SCHEMA,
+    SHARED_KEYS,
+    "is shared by every profile and can only be set at the top level",
+);
+
 #[derive(Clone, Deserialize, Debug)]
 pub struct RecorderConfig {
     write_delay: u64,
+    #[serde(default)]
     productive: Vec<String>,
+    #[serde(default)]
+    productive_titles: Vec<String>,
+    #[serde(default)]
+    weekdays: Vec<WeekdayProductive>,
+    #[serde(default)]
+    rules: Vec<Rule>,
+    #[serde(default)]
+    categories: Vec<Category>,
+    #[serde(default = "default_weight")]
+    default_weight: f64,
+    #[serde(default)]
+    identity: Identity,
+    #[serde(default)]
+    process_name: ProcessName,
+    #[serde(default)]
+    terminals: Vec<String>,
+    #[serde(default)]
+    browsers: Vec<String>,
+    #[serde(default)]
+    storage: StorageKind,
 }
 
 impl RecorderConfig {
+    // The same config with the shared keys taken from the other one
+    pub fn with_shared(mut self, other: &RecorderConfig) -> Self {
+        self.write_delay = other.write_delay;
+        self.identity = other.identity;
+        self.process_name = other.process_name;
+        self.storage = other.storage;
+        self
+    }
+
     pub fn productive(&self) -> &Vec<String> {
         &self.productive
     }
 
+    // Window titles containing any of these are productive
+    pub fn productive_titles(&self) -> &Vec<String> {
+        &self.productive_titles
+    }
+
+    pub fn weekdays(&self) -> &Vec<WeekdayProductive> {
+        &self.weekdays
+    }
+
+    // The default productive lists layered with the weekday's own
+    pub fn productive_on(&self, weekday: Weekday) -> (Vec<&String>, Vec<&String>) {
+        let mut apps: Vec<&String> = self.productive.iter().collect();
+        let mut titles: Vec<&String> = self.productive_titles.iter().collect();
+        for day in self.weekdays.iter().filter(|d| d.weekday == weekday) {
+            apps.extend(day.productive.iter());
+            titles.extend(day.productive_titles.iter());
+        }
+        (apps, titles)
+    }
+
+    pub fn rules(&self) -> &Vec<Rule> {
+        &self.rules
+    }
+
+    pub fn categories(&self) -> &Vec<Category> {
+        &self.categories
+    }
+
+    // Weight of a category by name, including the built in ones
+    pub fn category_weight(&self, name: &str) -> Option<f64> {
+        match name {
+            PRODUCTIVE => Some(1.0),
+            UNCATEGORIZED => Some(self.default_weight),
+            _ => self
+                .categories
+                .iter()
+                .find(|c| c.name == name)
+                .map(|c| c.weight),
+        }
+    }
+
+    // Weight of everything that isn't in a category or productive
+    pub fn default_weight(&self) -> f64 {
+        self.default_weight
+    }
+
+    // The category and weight of a window, any of its names can be listed.
+    // Categories are checked in order, the weekday's productive lists act
+    // as a last category with a weight of 1.0.
+    pub fn classify(&self, names: &[&str], title: &str, weekday: Weekday) -> (&str, f64) {
+        if let Some(c) = self.categories.iter().find(|c| c.matches(names, title)) {
+            return (&c.name, c.weight);
+        }
+
+        let (apps, titles) = self.productive_on(weekday);
+        let productive = apps.iter().any(|p| names.contains(&p.as_str()))
+            || titles.iter().any(|t| title.contains(t.as_str()));
+        if productive {
+            (PRODUCTIVE, 1.0)
+        } else {
+            (UNCATEGORIZED, self.default_weight)
+        }
+    }
+
+    pub fn identity(&self) -> Identity {
+        self.identity
+    }
+
+    pub fn process_name(&self) -> ProcessName {
+        self.process_name
+    }
+
+    // Terminal emulators whose time goes to their foreground job
+    pub fn terminals(&self) -> &Vec<String> {
+        &self.terminals
+    }
+
+    // Browsers whose time goes to the active tab's domain
+    pub fn browsers(&self) -> &Vec<String> {
+        &self.browsers
+    }
+
     pub fn write_delay(&self) -> u64 {
         self.write_delay
     }
+
+    pub fn storage(&self) -> StorageKind {
+        self.storage
+    }
 }
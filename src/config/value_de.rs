@@ -0,0 +1,355 @@
+use super::diagnostics::KeyPath;
+
+use std::fmt;
+
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    Unexpected, VariantAccess, Visitor,
+};
+use toml::value::{Table, Value};
+
+// A step into a table or a list
+#[derive(Debug)]
+enum Step {
+    Key(String),
+    Index(usize),
+}
+
+// A value in the merged config that doesn't fit the type it's read as,
+// and where it is
+#[derive(Debug)]
+pub struct TypeError {
+    // Innermost first, added to as it's passed up
+    steps: Vec<Step>,
+    message: String,
+}
+
+impl TypeError {
+    pub fn path(&self, prefix: &KeyPath) -> KeyPath {
+        self.steps
+            .iter()
+            .rev()
+            .fold(prefix.to_owned(), |path, step| match step {
+                Step::Key(k) => path.key(k),
+                Step::Index(i) => path.index(*i),
+            })
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn at(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path(&KeyPath::new()), self.message)
+    }
+}
+
+impl std::error::Error for TypeError {}
+
+impl de::Error for TypeError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        TypeError {
+            steps: Vec::new(),
+            message: message.to_string(),
+        }
+    }
+}
+
+// Reads the value as T like Value::try_into, but the error says
+// which key it's about instead of only naming it in its message
+pub fn from_value<T: DeserializeOwned>(value: Value) -> Result<T, TypeError> {
+    T::deserialize(ValueDeserializer(value))
+}
+
+fn unexpected(value: &Value) -> Unexpected<'_> {
+    match value {
+        Value::String(s) => Unexpected::Str(s),
+        Value::Integer(i) => Unexpected::Signed(*i),
+        Value::Float(f) => Unexpected::Float(*f),
+        Value::Boolean(b) => Unexpected::Bool(*b),
+        Value::Datetime(_) => Unexpected::Other("datetime"),
+        Value::Array(_) => Unexpected::Seq,
+        Value::Table(_) => Unexpected::Map,
+    }
+}
+
+struct ValueDeserializer(Value);
+
+impl<'de> de::Deserializer<'de> for ValueDeserializer {
+    type Error = TypeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypeError> {
+        match self.0 {
+            Value::String(s) => visitor.visit_string(s),
+            Value::Integer(i) => visitor.visit_i64(i),
+            Value::Float(f) => visitor.visit_f64(f),
+            Value::Boolean(b) => visitor.visit_bool(b),
+            // Times are read from strings
+            Value::Datetime(d) => visitor.visit_string(d.to_string()),
+            Value::Array(a) => visitor.visit_seq(Seq {
+                values: a.into_iter().enumerate(),
+            }),
+            Value::Table(t) => visitor.visit_map(Map {
+                entries: t.into_iter(),
+                value: None,
+            }),
+        }
+    }
+
+    // Missing keys are None, anything that's there is Some
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, TypeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, TypeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    // A string names a variant without data, a table with one key one with it
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TypeError> {
+        match self.0 {
+            Value::String(s) => visitor.visit_enum(s.into_deserializer()),
+            Value::Table(t) if t.len() == 1 => {
+                let (variant, value) = t.into_iter().next().unwrap();
+                visitor.visit_enum(Enum { variant, value })
+            }
+            other => Err(de::Error::invalid_type(
+                unexpected(&other),
+                &"a string or a table with one key",
+            )),
+        }
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+struct Seq {
+    values: std::iter::Enumerate<std::vec::IntoIter<Value>>,
+}
+
+impl<'de> SeqAccess<'de> for Seq {
+    type Error = TypeError;
+
+    fn next_element_seed<S: DeserializeSeed<'de>>(
+        &mut self,
+        seed: S,
+    ) -> Result<Option<S::Value>, TypeError> {
+        match self.values.next() {
+            Some((i, v)) => seed
+                .deserialize(ValueDeserializer(v))
+                .map(Some)
+                .map_err(|e| e.at(Step::Index(i))),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+struct Map {
+    entries: <Table as IntoIterator>::IntoIter,
+    // The value of the key last read
+    value: Option<(String, Value)>,
+}
+
+impl<'de> MapAccess<'de> for Map {
+    type Error = TypeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, TypeError> {
+        let (key, value) = match self.entries.next() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        let read = seed
+            .deserialize(key.to_owned().into_deserializer())
+            .map_err(|e: TypeError| e.at(Step::Key(key.to_owned())))?;
+        self.value = Some((key, value));
+        Ok(Some(read))
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, TypeError> {
+        let (key, value) = self
+            .value
+            .take()
+            .ok_or_else(|| de::Error::custom("a value was read before its key"))?;
+        seed.deserialize(ValueDeserializer(value))
+            .map_err(|e| e.at(Step::Key(key)))
+    }
+}
+
+struct Enum {
+    variant: String,
+    value: Value,
+}
+
+impl<'de> EnumAccess<'de> for Enum {
+    type Error = TypeError;
+    type Variant = Enum;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Enum), TypeError> {
+        let variant = seed
+            .deserialize(self.variant.to_owned().into_deserializer())
+            .map_err(|e: TypeError| e.at(Step::Key(self.variant.to_owned())))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for Enum {
+    type Error = TypeError;
+
+    fn unit_variant(self) -> Result<(), TypeError> {
+        let e: TypeError = de::Error::invalid_type(unexpected(&self.value), &"a string");
+        Err(e.at(Step::Key(self.variant)))
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, TypeError> {
+        let Enum { variant, value } = self;
+        seed.deserialize(ValueDeserializer(value))
+            .map_err(|e| e.at(Step::Key(variant)))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, TypeError> {
+        let Enum { variant, value } = self;
+        de::Deserializer::deserialize_any(ValueDeserializer(value), visitor)
+            .map_err(|e| e.at(Step::Key(variant)))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, TypeError> {
+        let Enum { variant, value } = self;
+        de::Deserializer::deserialize_any(ValueDeserializer(value), visitor)
+            .map_err(|e| e.at(Step::Key(variant)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::date_config::{Date, Hours};
+
+    use chrono::{Month, NaiveTime, Weekday};
+    use serde::Deserialize;
+
+    #[derive(Deserialize, Debug)]
+    struct Config {
+        outer: Outer,
+        #[serde(default)]
+        hours: Vec<Hours>,
+        #[serde(default)]
+        dates: Vec<Date>,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Outer {
+        inner: Inner,
+    }
+
+    #[derive(Deserialize, Debug)]
+    struct Inner {
+        weight: f64,
+    }
+
+    fn read(text: &str) -> Result<Config, TypeError> {
+        from_value(toml::from_str(text).unwrap())
+    }
+
+    fn path(e: &TypeError) -> String {
+        e.path(&KeyPath::new()).to_string()
+    }
+
+    #[test]
+    fn integers_are_read_as_floats() {
+        let config = read("[outer.inner]\nweight = 2\n").unwrap();
+        assert_eq!(config.outer.inner.weight, 2.0);
+    }
+
+    #[test]
+    fn errors_in_nested_tables_have_their_path() {
+        let e = read("[outer.inner]\nweight = \"heavy\"\n").unwrap_err();
+        assert_eq!(path(&e), "outer.inner.weight");
+        assert!(e.message().contains("heavy"), "{}", e.message());
+    }
+
+    #[test]
+    fn datetimes_are_read_as_strings() {
+        let config = read(
+            "[outer.inner]\nweight = 1.0\n[[hours]]\nweekday = \"Mon\"\nstart = 09:00:00\nstop = 17:30:00\n",
+        )
+        .unwrap();
+        assert_eq!(
+            config.hours[0].start(),
+            NaiveTime::from_hms_opt(9, 0, 0).unwrap()
+        );
+        assert_eq!(
+            config.hours[0].stop(),
+            NaiveTime::from_hms_opt(17, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn errors_in_arrays_of_tables_have_their_index() {
+        let e = read(
+            "[outer.inner]\nweight = 1.0\n[[hours]]\nweekday = \"Mon\"\nstart = 09:00:00\nstop = 17:00:00\n[[hours]]\nweekday = \"Someday\"\nstart = 09:00:00\nstop = 17:00:00\n",
+        )
+        .unwrap_err();
+        assert_eq!(path(&e), "hours[1].weekday");
+    }
+
+    #[test]
+    fn untagged_enums_pick_the_variant_that_fits() {
+        let config = read(
+            "[outer.inner]\nweight = 1.0\n[[dates]]\nmonth = \"December\"\nday = 25\n[[dates]]\nmonth = \"November\"\nweek = 4\nday = \"Thu\"\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            config.dates[0],
+            Date::MonthDay {
+                month: Month::December,
+                day: 25
+            }
+        ));
+        assert!(matches!(
+            config.dates[1],
+            Date::MonthWeekDay {
+                month: Month::November,
+                week: 4,
+                day: Weekday::Thu
+            }
+        ));
+
+        let e = read("[outer.inner]\nweight = 1.0\n[[dates]]\nmonth = \"December\"\n").unwrap_err();
+        assert_eq!(path(&e), "dates[0]");
+    }
+}
@@ -0,0 +1,2 @@
+// The config is shared by the daemon and the browser's native messaging host
+pub mod config;
@@ -1,11 +1,18 @@
-pub mod config;
+mod cli;
 mod server;
 
-use server::{Server, ServerResult};
+use septem::config;
+
+use std::process;
+
+use clap::Parser;
+use cli::Cli;
 
 #[tokio::main]
-async fn main() -> ServerResult<()> {
-    Server::new(None)?.await?;
-    println!("Main End");
-    Ok(())
+async fn main() {
+    // Printed as is, config problems are one per line
+    if let Err(e) = Cli::parse().run().await {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
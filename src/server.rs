@@ -1,23 +1,40 @@
 mod alert;
+mod browser_listener;
+mod budget;
 mod client;
+mod config_watcher;
+mod control;
 mod date_checker;
 mod event_handler;
+mod idle_watcher;
+mod profiles;
 mod recorder;
+mod reloader;
 mod signal_handler;
 
-use crate::config::{Config, ConfigError};
+use crate::config::{diagnostics::KeyPath, Config, ConfigError};
 
 use alert::{AlertError, Alerter};
-use client::{Client, ClientError, Pid, Productive, Running, Timeout};
+use browser_listener::{BrowserError, BrowserListener};
+use budget::{BudgetChecker, BudgetError};
+use client::{ActivityChannel, Client, ClientError, Idle, Running, Score, Shared, Timeout, Usage};
+use config_watcher::{ConfigWatcher, WatchError};
+use control::{ControlError, ControlListener};
 use date_checker::{DateChecker, DateError};
 use event_handler::{EventError, EventHandler};
+use idle_watcher::{IdleError, IdleWatcher};
+use profiles::ProfileScheduler;
 use recorder::{Recorder, RecorderError};
+use reloader::Reloader;
 use signal_handler::{SignalError, SignalHandler};
 
+use std::collections::HashSet;
 use std::future::Future;
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
+use chrono::Local;
 use futures::{
     future::{try_join_all, TryJoinAll},
     FutureExt,
@@ -30,6 +47,9 @@ use thiserror::Error;
 
 use self::client::ClientResult;
 
+pub use control::request;
+pub use recorder::{hms, print_report, week_of};
+
 #[derive(Debug, Error)]
 pub enum ServerError {
     // Error when joining threads
@@ -44,9 +64,18 @@ pub enum ServerError {
     #[error("{0}")]
     StartUpAlertError(#[from] AlertError),
 
+    #[error("{0}")]
+    StartUpBrowserError(#[from] BrowserError),
+
+    #[error("{0}")]
+    StartUpBudgetError(#[from] BudgetError),
+
     #[error("{0}")]
     StartUpConfigError(#[from] ConfigError),
 
+    #[error("{0}")]
+    StartUpControlError(#[from] ControlError),
+
     #[error("{0}")]
     StartUpDateError(#[from] DateError),
 
@@ -56,8 +85,14 @@ pub enum ServerError {
     #[error("{0}")]
     StartUpEventError(#[from] EventError),
 
+    #[error("{0}")]
+    StartUpIdleError(#[from] IdleError),
+
     #[error("{0}")]
     StartUpSignalError(#[from] SignalError),
+
+    #[error("{0}")]
+    StartUpWatchError(#[from] WatchError),
 }
 
 pub type ServerResult<T> = Result<T, ServerError>;
@@ -74,36 +109,125 @@ pub struct Server {
 }
 
 impl Server {
-    pub fn new(config_file: Option<String>) -> ServerResult<Server> {
-        let config = Config::new(config_file.clone())?;
+    pub fn new(config_file: Option<String>, data_dir: Option<String>) -> ServerResult<Server> {
+        let mut config = Config::new(config_file.clone())?;
+        check_config(&config)?;
+        if let Some(d) = data_dir {
+            config.set_share(d);
+        }
+        config.create_dirs()?;
         let share = config.share()?;
-        let a_conf = config.alert_config();
-        let d_conf = config.date_config();
-        let r_conf = config.recorder_config();
+        let runtime = Config::runtime()?;
+        let state = PathBuf::from(Config::state()?);
+
+        // A profile chosen by hand is kept until another is chosen
+        let profiles = config.profiles();
+        let chosen = profiles::remembered(&state);
+        let (active, profile) = profiles.active(chosen.as_deref(), Local::now().naive_local());
+        let a_conf = Shared::new(profile.alert_config());
+        let b_conf = config.budget_config();
+        let d_conf = Shared::new(profile.date_config());
+        let i_conf = config.idle_config();
+        let r_conf = profile.recorder_config();
 
         let running = Running::new(true);
         let timeout = Timeout::new();
 
-        let pid = Pid::new();
-        let prod = Productive::new(false);
+        let activity = ActivityChannel::new();
+        let score = Score::new(0.0);
+        let idle = Idle::new(false);
         let alerts_on = Running::new(true);
+        let usage = Usage::new();
+        let budgets = Shared::new(b_conf.clone());
+        let date_reloaded = Timeout::new();
+        let profiles_reloaded = Timeout::new();
+        let reloader = Reloader::new(
+            config_file.clone(),
+            activity.0.clone(),
+            a_conf.clone(),
+            d_conf.clone(),
+            budgets.clone(),
+            date_reloaded.clone(),
+            profiles_reloaded.clone(),
+            profiles,
+            chosen,
+            active.to_owned(),
+            state,
+        );
 
-        let event = EventHandler::new(pid.0.clone(), running.clone())?;
+        let event = EventHandler::new(activity.0.clone(), running.clone())?;
         let window = event.window();
 
-        let signal = SignalHandler::new(running.clone())?;
-        let recorder = Recorder::new(share, r_conf, pid.1, running.clone(), prod.clone())?;
-        let date = DateChecker::new(d_conf, running.clone(), alerts_on.clone(), timeout.clone())?;
-        let alert = Alerter::new(a_conf, running.clone(), alerts_on, prod)?;
+        let signal = SignalHandler::new(running.clone(), reloader.clone())?;
+        let idle_watch =
+            IdleWatcher::new(i_conf, activity.0.clone(), running.clone(), idle.clone())?;
+        let browser = BrowserListener::new(
+            runtime.to_owned().into(),
+            activity.0.clone(),
+            running.clone(),
+            timeout.clone(),
+        )?;
+        let control = ControlListener::new(
+            runtime.into(),
+            reloader.clone(),
+            activity.0,
+            running.clone(),
+            timeout.clone(),
+            score.clone(),
+            idle.clone(),
+            usage.clone(),
+        )?;
+        let recorder = Recorder::new(
+            share,
+            r_conf,
+            b_conf,
+            active,
+            activity.1,
+            running.clone(),
+            score.clone(),
+            usage.clone(),
+        )?;
+        let budget = BudgetChecker::new(budgets, usage, running.clone())?;
+        let date = DateChecker::new(
+            d_conf,
+            running.clone(),
+            alerts_on.clone(),
+            timeout.clone(),
+            date_reloaded,
+        )?;
+        let alert = Alerter::new(a_conf, running.clone(), alerts_on, score, idle)?;
+        let scheduler = ProfileScheduler::new(
+            reloader.clone(),
+            running.clone(),
+            timeout.clone(),
+            profiles_reloaded,
+        );
 
         let sig_handle = signal.handle();
-        let clients = try_join_all(vec![
+        let mut threads = vec![
             spawn(event.start()),
             spawn(signal.start()),
+            spawn(idle_watch.start()),
+            spawn(browser.start()),
+            spawn(control.start()),
             spawn(recorder.start()),
             spawn(date.start()),
             spawn(alert.start()),
-        ]);
+            spawn(budget.start()),
+            spawn(scheduler.start()),
+        ];
+        if config.watch_config() {
+            let files = config.files().into_iter().map(PathBuf::from).collect();
+            let watch = ConfigWatcher::new(
+                files,
+                config.patterns(),
+                reloader,
+                running.clone(),
+                timeout.clone(),
+            )?;
+            threads.push(spawn(watch.start()));
+        }
+        let clients = try_join_all(threads);
 
         Ok(Server {
             config_file,
@@ -145,6 +269,48 @@ impl Server {
     }
 }
 
+fn described<E: ToString>(problems: Vec<(KeyPath, E)>) -> Vec<(KeyPath, String)> {
+    problems
+        .into_iter()
+        .map(|(path, e)| (path, e.to_string()))
+        .collect()
+}
+
+// Runs every client's checks of its config without starting any of them,
+// all the problems found are listed together
+pub fn check_config(config: &Config) -> ServerResult<()> {
+    let mut problems = described(Recorder::problems(&config.recorder_config()));
+    problems.extend(described(DateChecker::problems(&config.date_config())));
+    problems.extend(described(Alerter::problems(&config.alert_config())));
+    problems.extend(described(IdleWatcher::problems(&config.idle_config())));
+    problems.extend(described(BudgetChecker::problems(&config.budget_config())));
+
+    // Profiles get the top level's problems too, those are listed once
+    let profiles = config.profiles();
+    problems.extend(described(profiles::problems(&profiles)));
+    let seen: HashSet<(String, String)> = problems
+        .iter()
+        .map(|(path, e)| (path.to_string(), e.to_owned()))
+        .collect();
+    for (name, profile) in profiles.named() {
+        let prefix = KeyPath::new().key("profiles").key(name);
+        let mut found = described(Recorder::problems(&profile.recorder_config()));
+        found.extend(described(DateChecker::problems(&profile.date_config())));
+        found.extend(described(Alerter::problems(&profile.alert_config())));
+        for (path, e) in found {
+            if !seen.contains(&(path.to_string(), e.to_owned())) {
+                problems.push((prefix.join(&path), e));
+            }
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(config.diagnose(problems).into())
+    }
+}
+
 fn check_clients(clients_result: Result<Vec<ClientResult<()>>, JoinError>) -> ServerResult<()> {
     let clients = clients_result?;
     for c in clients.into_iter() {
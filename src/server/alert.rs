@@ -1,5 +1,6 @@
 use crate::config::alert_config::AlertConfig;
-use crate::server::client::{Client, ClientResult, Productive, Running};
+use crate::config::diagnostics::{first, KeyPath};
+use crate::server::client::{Client, ClientResult, Idle, Running, Score, Shared};
 
 use std::time::Duration;
 use tokio::time::sleep;
@@ -18,32 +19,40 @@ pub type AlertResult<T> = Result<T, AlertError>;
 pub struct Alerter {
     running: Running,
     alerts_on: Running,
-    is_prod: Productive,
-    config: AlertConfig,
+    score: Score,
+    idle: Idle,
+    config: Shared<AlertConfig>,
     productive: f64,
     unproductive: f64,
 }
 
 impl Alerter {
-    fn sanity_check_conf(conf: &AlertConfig) -> AlertResult<()> {
+    pub fn sanity_check_conf(conf: &AlertConfig) -> AlertResult<()> {
+        first(Alerter::problems(conf))
+    }
+
+    pub fn problems(conf: &AlertConfig) -> Vec<(KeyPath, AlertError)> {
+        let mut problems = Vec::new();
         if conf.message().is_empty() {
-            Err(AlertError::EmptyMessageError)
-        } else {
-            Ok(())
+            let path = KeyPath::new().key("alerts").key("message");
+            problems.push((path, AlertError::EmptyMessageError));
         }
+        problems
     }
 
     pub fn new(
-        config: AlertConfig,
+        config: Shared<AlertConfig>,
         running: Running,
         alerts_on: Running,
-        is_prod: Productive,
+        score: Score,
+        idle: Idle,
     ) -> AlertResult<Alerter> {
-        Alerter::sanity_check_conf(&config)?;
+        Alerter::sanity_check_conf(&config.load())?;
         Ok(Alerter {
             running,
             alerts_on,
-            is_prod,
+            score,
+            idle,
             config,
             productive: 0.0,
             unproductive: 0.0,
@@ -55,20 +64,27 @@ impl Alerter {
 impl Client for Alerter {
     async fn start(mut self) -> ClientResult<()> {
         while self.running.load() {
-            sleep(Duration::from_millis(self.config.delay())).await;
-            if self.alerts_on.load() {
-                if self.is_prod.load() {
-                    self.productive += self.config.delay() as f64 / 1000.0;
-                    if self.productive >= self.config.productive_time() * 60.0 {
+            // A reload keeps the counts
+            let config = self.config.load();
+            sleep(Duration::from_millis(config.delay())).await;
+            // Time away from the computer is neither productive nor not
+            if self.alerts_on.load() && !self.idle.load() {
+                // Time counts as much as the window's weight,
+                // neutral windows don't count either way
+                let score = self.score.load();
+                let time = config.delay() as f64 / 1000.0 * score.abs();
+                if score > 0.0 {
+                    self.productive += time;
+                    if self.productive >= config.productive_time() * 60.0 {
                         self.productive = 0.0;
                         self.unproductive = 0.0;
                     }
-                } else {
-                    self.unproductive += self.config.delay() as f64 / 1000.0;
-                    if self.unproductive >= self.config.unproductive_time() * 60.0 {
+                } else if score < 0.0 {
+                    self.unproductive += time;
+                    if self.unproductive >= config.unproductive_time() * 60.0 {
                         self.productive = 0.0;
                         self.unproductive = 0.0;
-                        println!("{}", self.config.message());
+                        println!("{}", config.message());
                     }
                 }
             }
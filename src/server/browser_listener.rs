@@ -0,0 +1,100 @@
+use crate::config::dirs::BROWSER_SOCKET;
+use crate::server::client::{Activity, ActivitySender, Client, ClientResult, Running, Timeout};
+
+use std::fs::remove_file;
+use std::io;
+use std::path::PathBuf;
+
+use futures::future::FutureExt;
+use futures::select_biased;
+use serde::Deserialize;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum BrowserError {
+    #[error("Failed to listen on the browser socket {0}:\n{1}")]
+    BindError(String, io::Error),
+
+    #[error("{0}")]
+    AcceptError(#[from] io::Error),
+}
+
+type BrowserResult<T> = Result<T, BrowserError>;
+
+// What the native messaging host forwards, one per line
+#[derive(Deserialize, Debug)]
+struct TabMessage {
+    domain: Option<String>,
+}
+
+// Receives the active tab's domain from septem-native-host
+pub struct BrowserListener {
+    sender: ActivitySender,
+    running: Running,
+    timeout: Timeout,
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl BrowserListener {
+    pub fn new(
+        runtime: PathBuf,
+        sender: ActivitySender,
+        running: Running,
+        timeout: Timeout,
+    ) -> BrowserResult<BrowserListener> {
+        let path = runtime.join(BROWSER_SOCKET);
+        let bind_error = |e| BrowserError::BindError(path.to_string_lossy().into_owned(), e);
+
+        // Left behind if we weren't shut down cleanly
+        if path.exists() {
+            remove_file(&path).map_err(bind_error)?;
+        }
+        let listener = UnixListener::bind(&path).map_err(bind_error)?;
+
+        Ok(BrowserListener {
+            sender,
+            running,
+            timeout,
+            path,
+            listener,
+        })
+    }
+
+    async fn read_tabs(stream: UnixStream, sender: ActivitySender) {
+        let mut lines = BufReader::new(stream).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            match serde_json::from_str::<TabMessage>(&line) {
+                Ok(tab) => {
+                    if sender.send(Activity::Domain(tab.domain)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(e) => println!("Ignoring bad message from the browser: {}", e),
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Client for BrowserListener {
+    async fn start(self) -> ClientResult<()> {
+        while self.running.load() {
+            select_biased! {
+                _ = self.timeout.wait().fuse() => {}
+                conn = self.listener.accept().fuse() => {
+                    let (stream, _) = conn.map_err(BrowserError::from)?;
+                    tokio::spawn(BrowserListener::read_tabs(stream, self.sender.clone()));
+                }
+            }
+        }
+
+        let _ = remove_file(&self.path);
+        println!("Browser End");
+        Ok(())
+    }
+}
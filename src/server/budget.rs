@@ -0,0 +1,253 @@
+use crate::config::budget_config::Budget;
+use crate::config::diagnostics::{first, KeyPath};
+use crate::server::client::{Client, ClientResult, DayTotals, Running, Shared, Usage};
+
+use std::collections::HashSet;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use async_trait::async_trait;
+use chrono::{Local, NaiveDate};
+use thiserror::Error;
+
+// Milliseconds between checks of today's usage
+const CHECK_DELAY: u64 = 1000;
+
+#[derive(Error, Debug)]
+pub enum BudgetError {
+    #[error("Every budget must be for either an app or a category")]
+    TargetError,
+
+    #[error("The budget for {0} has neither a max nor a min")]
+    NoLimitError(String),
+
+    #[error("The budget for {0} has a time to meet a min by, but no min")]
+    ByWithoutMinError(String),
+
+    #[error("The budget for {0} has a negative max or min")]
+    NegativeLimitError(String),
+
+    #[error("The budget for {0} has its min > max")]
+    FlipFlopLimitError(String),
+}
+
+pub type BudgetResult<T> = Result<T, BudgetError>;
+
+// Whether time recorded under the key is time in the app. Keys can name
+// a tab or job after the app, ie firefox:docs.rs, and the app by its class
+// and process, ie Slack/slack, either of which the budget can be for.
+pub fn is_for_app(app: &str, key: &str) -> bool {
+    let name = key.split(':').next().unwrap_or(key);
+    key == app
+        || key.starts_with(&format!("{}:", app))
+        || name
+            .split_once('/')
+            .is_some_and(|(c, p)| c == app || p == app)
+}
+
+// Seconds of the day spent on what the budget is for
+pub fn used(budget: &Budget, totals: &DayTotals) -> u64 {
+    match (budget.app(), budget.category()) {
+        (Some(a), _) => totals
+            .names
+            .iter()
+            .filter(|(name, _)| is_for_app(a, name))
+            .map(|(_, secs)| secs)
+            .sum(),
+        (None, Some(c)) => totals.categories.get(c).copied().unwrap_or(0),
+        (None, None) => 0,
+    }
+}
+
+pub struct BudgetChecker {
+    budgets: Shared<Vec<Budget>>,
+    usage: Usage,
+    running: Running,
+    day: NaiveDate,
+    // Budgets already alerted about today
+    over: HashSet<usize>,
+    short: HashSet<usize>,
+}
+
+impl BudgetChecker {
+    pub fn sanity_check(budgets: &[Budget]) -> BudgetResult<()> {
+        first(BudgetChecker::problems(budgets))
+    }
+
+    pub fn problems(budgets: &[Budget]) -> Vec<(KeyPath, BudgetError)> {
+        let mut problems = Vec::new();
+        for (i, b) in budgets.iter().enumerate() {
+            let path = KeyPath::new().key("budgets").index(i);
+            let name = b.name().to_owned();
+            if b.app().is_some() == b.category().is_some() {
+                problems.push((path.clone(), BudgetError::TargetError));
+            }
+            match (b.min(), b.max()) {
+                (None, None) => {
+                    problems.push((path.clone(), BudgetError::NoLimitError(name.clone())))
+                }
+                (Some(min), _) if min < 0.0 => problems.push((
+                    path.key("min"),
+                    BudgetError::NegativeLimitError(name.clone()),
+                )),
+                (_, Some(max)) if max < 0.0 => problems.push((
+                    path.key("max"),
+                    BudgetError::NegativeLimitError(name.clone()),
+                )),
+                (Some(min), Some(max)) if min > max => problems.push((
+                    path.key("min"),
+                    BudgetError::FlipFlopLimitError(name.clone()),
+                )),
+                _ => {}
+            }
+            if b.by().is_some() && b.min().is_none() {
+                problems.push((path.key("by"), BudgetError::ByWithoutMinError(name)));
+            }
+        }
+        problems
+    }
+
+    pub fn new(
+        budgets: Shared<Vec<Budget>>,
+        usage: Usage,
+        running: Running,
+    ) -> BudgetResult<BudgetChecker> {
+        BudgetChecker::sanity_check(&budgets.load())?;
+        Ok(BudgetChecker {
+            budgets,
+            usage,
+            running,
+            day: Local::now().date_naive(),
+            over: HashSet::new(),
+            short: HashSet::new(),
+        })
+    }
+
+    fn check(&mut self) {
+        let now = Local::now();
+        if now.date_naive() != self.day {
+            self.day = now.date_naive();
+            self.over.clear();
+            self.short.clear();
+        }
+
+        // The recorder may not have caught up with the day yet
+        let today = self.usage.load();
+        let totals = if today.day == self.day {
+            today.totals
+        } else {
+            DayTotals::default()
+        };
+
+        for (i, b) in self.budgets.load().iter().enumerate() {
+            let mut secs = used(b, &totals);
+            if let Some((name, category, start)) = &today.open {
+                let is_for = match b.app() {
+                    Some(a) => is_for_app(a, name),
+                    None => b.category() == Some(category),
+                };
+                if is_for && start.date_naive() == self.day {
+                    secs += (now - *start).num_seconds().max(0) as u64;
+                }
+            }
+            let minutes = secs as f64 / 60.0;
+
+            if let Some(max) = b.max() {
+                if minutes >= max && self.over.insert(i) {
+                    println!(
+                        "{} went over its budget of {} minutes for today",
+                        b.name(),
+                        max
+                    );
+                }
+            }
+            if let (Some(min), Some(by)) = (b.min(), b.by()) {
+                if now.time() >= by && minutes < min && self.short.insert(i) {
+                    println!(
+                        "{} has had {:.0} of its {} minutes for today by {}",
+                        b.name(),
+                        minutes,
+                        min,
+                        by
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Client for BudgetChecker {
+    async fn start(mut self) -> ClientResult<()> {
+        while self.running.load() {
+            sleep(Duration::from_millis(CHECK_DELAY)).await;
+            self.check();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn budget(text: &str) -> Budget {
+        toml::from_str(text).unwrap()
+    }
+
+    fn totals() -> DayTotals {
+        let mut totals = DayTotals::default();
+        totals.add("firefox:docs.rs", "productive", 600);
+        totals.add("firefox:youtube.com", "uncategorized", 300);
+        totals.add("firefox-esr", "uncategorized", 60);
+        totals.add("Slack/slack", "productive", 120);
+        totals.add("alacritty:nvim", "productive", 900);
+        totals
+    }
+
+    #[test]
+    fn apps_count_every_key_they_are_part_of() {
+        let used = |app: &str| used(&budget(&format!("app = {:?}\nmax = 1.0", app)), &totals());
+        assert_eq!(used("firefox"), 900);
+        assert_eq!(used("firefox:docs.rs"), 600);
+        assert_eq!(used("Slack"), 120);
+        assert_eq!(used("slack"), 120);
+        assert_eq!(used("nvim"), 0);
+        assert_eq!(used("alacritty"), 900);
+        assert_eq!(used("vim"), 0);
+    }
+
+    #[test]
+    fn categories_count_their_time() {
+        let b = budget("category = \"productive\"\nmin = 30.0");
+        assert_eq!(used(&b, &totals()), 1620);
+    }
+
+    #[test]
+    fn problems_point_at_the_budget() {
+        let budgets = vec![
+            budget("app = \"firefox\"\nmax = 60.0"),
+            budget("max = 60.0"),
+            budget("app = \"firefox\"\ncategory = \"productive\"\nmax = 60.0"),
+            budget("app = \"slack\""),
+            budget("app = \"slack\"\nmin = -1.0"),
+            budget("app = \"slack\"\nmin = 90.0\nmax = 60.0"),
+            budget("app = \"slack\"\nmax = 60.0\nby = \"17:00:00\""),
+        ];
+        let problems: Vec<String> = BudgetChecker::problems(&budgets)
+            .iter()
+            .map(|(path, e)| format!("{}: {}", path, e))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "budgets[1]: Every budget must be for either an app or a category",
+                "budgets[2]: Every budget must be for either an app or a category",
+                "budgets[3]: The budget for slack has neither a max nor a min",
+                "budgets[4].min: The budget for slack has a negative max or min",
+                "budgets[5].min: The budget for slack has its min > max",
+                "budgets[6].by: The budget for slack has a time to meet a min by, but no min",
+            ]
+        );
+    }
+}
@@ -1,21 +1,25 @@
 #![allow(dead_code)]
 
+use crate::config::{budget_config::Budget, recorder_config::RecorderConfig};
 use crate::server::{
-    alert::AlertError, event_handler::EventError, recorder::RecorderError,
-    signal_handler::SignalError,
+    alert::AlertError, browser_listener::BrowserError, budget::BudgetError,
+    config_watcher::WatchError, control::ControlError, event_handler::EventError,
+    idle_watcher::IdleError, recorder::RecorderError, signal_handler::SignalError,
 };
 
+use std::collections::HashMap;
 use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    Arc, Mutex, RwLock,
 };
-use tokio::sync::{mpsc, Notify};
+use tokio::sync::{mpsc, oneshot, Notify};
 use tokio::time::{sleep_until, Duration, Instant};
 
 use futures::future::FutureExt;
 use futures::select_biased;
 
 use async_trait::async_trait;
+use chrono::{DateTime, Local, NaiveDate};
 use thiserror::Error;
 use tokio::task::JoinError;
 
@@ -30,31 +34,86 @@ pub enum ClientError {
     #[error("{0}")]
     AlertClientError(#[from] AlertError),
 
+    #[error("{0}")]
+    BrowserClientError(#[from] BrowserError),
+
+    #[error("{0}")]
+    BudgetClientError(#[from] BudgetError),
+
+    #[error("{0}")]
+    ControlClientError(#[from] ControlError),
+
     #[error("{0}")]
     EventClientError(#[from] EventError),
 
+    #[error("{0}")]
+    IdleClientError(#[from] IdleError),
+
     #[error("{0}")]
     RecorderClientError(#[from] RecorderError),
 
     #[error("{0}")]
     SignalClientError(#[from] SignalError),
+
+    #[error("{0}")]
+    WatchClientError(#[from] WatchError),
 }
 
 pub type ClientResult<T> = Result<T, ClientError>;
 
-pub type PidSender = mpsc::Sender<Option<u32>>;
-pub type PidRecv = mpsc::Receiver<Option<u32>>;
+// The window that has focus
+#[derive(Clone, Debug)]
+pub struct ActiveWindow {
+    pub pid: u32,
+    pub title: String,
+    // WM_CLASS instance and class names
+    pub instance: String,
+    pub class: String,
+}
+
+// Requests from the control socket that the recorder handles
+#[derive(Debug)]
+pub enum Command {
+    Pause,
+    Resume,
+    // Writes everything recorded so far
+    Flush,
+    // New settings and the profile they're from
+    Reload(Box<RecorderConfig>, Vec<Budget>, String),
+}
+
+// Told if a command worked or why it didn't
+pub type Reply = oneshot::Sender<Result<(), String>>;
+
+// What the recorder is told about the user
+#[derive(Debug)]
+pub enum Activity {
+    // The new active window, if any
+    Focus(Option<ActiveWindow>),
+    // The active window's new title
+    Title(String),
+    // No input has been given since the time
+    Idle(DateTime<Local>),
+    // Input was given after being idle
+    Resume,
+    // Domain of the browser's active tab, if any
+    Domain(Option<String>),
+    Command(Command, Reply),
+}
+
+pub type ActivitySender = mpsc::Sender<Activity>;
+pub type ActivityRecv = mpsc::Receiver<Activity>;
 
 #[derive(Debug)]
-pub struct Pid(pub PidSender, pub PidRecv);
+pub struct ActivityChannel(pub ActivitySender, pub ActivityRecv);
 
-unsafe impl Send for Pid {}
-unsafe impl Sync for Pid {}
+unsafe impl Send for ActivityChannel {}
+unsafe impl Sync for ActivityChannel {}
 
-impl Pid {
+impl ActivityChannel {
     pub fn new() -> Self {
         let (tx, rx) = mpsc::channel(1);
-        Pid(tx, rx)
+        ActivityChannel(tx, rx)
     }
 }
 
@@ -68,7 +127,7 @@ unsafe impl Sync for Running {}
 
 // Each type is used for different purpose
 // Lets me know what to use the variable for
-pub type Productive = Running;
+pub type Idle = Running;
 
 impl Running {
     pub fn new(val: bool) -> Self {
@@ -86,6 +145,103 @@ impl Running {
     }
 }
 
+// Productivity weight of the focused window from -1.0 to 1.0
+#[derive(Clone, Debug)]
+pub struct Score {
+    val: Arc<AtomicU64>,
+}
+
+unsafe impl Send for Score {}
+unsafe impl Sync for Score {}
+
+impl Score {
+    pub fn new(val: f64) -> Self {
+        Self {
+            val: Arc::new(AtomicU64::new(val.to_bits())),
+        }
+    }
+
+    pub fn load(&self) -> f64 {
+        f64::from_bits(self.val.load(Ordering::SeqCst))
+    }
+
+    pub fn store(&self, val: f64) {
+        self.val.store(val.to_bits(), Ordering::SeqCst);
+    }
+}
+
+// Seconds spent on each name and category in a day
+#[derive(Clone, Debug, Default)]
+pub struct DayTotals {
+    pub names: HashMap<String, u64>,
+    pub categories: HashMap<String, u64>,
+}
+
+impl DayTotals {
+    pub fn add(&mut self, name: &str, category: &str, secs: u64) {
+        *self.names.entry(name.to_owned()).or_insert(0) += secs;
+        *self.categories.entry(category.to_owned()).or_insert(0) += secs;
+    }
+}
+
+// Today's totals as of the recorder's last event
+#[derive(Clone, Debug, Default)]
+pub struct Today {
+    pub day: NaiveDate,
+    pub totals: DayTotals,
+    // Name, category and start of the span that is still open
+    pub open: Option<(String, String, DateTime<Local>)>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Usage {
+    val: Arc<Mutex<Today>>,
+}
+
+unsafe impl Send for Usage {}
+unsafe impl Sync for Usage {}
+
+impl Usage {
+    pub fn new() -> Self {
+        Self {
+            val: Arc::new(Mutex::new(Today::default())),
+        }
+    }
+
+    pub fn load(&self) -> Today {
+        self.val.lock().unwrap().clone()
+    }
+
+    pub fn store(&self, val: Today) {
+        *self.val.lock().unwrap() = val;
+    }
+}
+
+// Config that can be swapped while the clients run
+#[derive(Clone, Debug)]
+pub struct Shared<T> {
+    val: Arc<RwLock<T>>,
+}
+
+unsafe impl<T> Send for Shared<T> {}
+unsafe impl<T> Sync for Shared<T> {}
+
+impl<T: Clone> Shared<T> {
+    pub fn new(val: T) -> Self {
+        Self {
+            val: Arc::new(RwLock::new(val)),
+        }
+    }
+
+    pub fn load(&self) -> T {
+        self.val.read().unwrap().clone()
+    }
+
+    pub fn store(&self, val: T) {
+        *self.val.write().unwrap() = val;
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Timeout {
     notify: Arc<Notify>,
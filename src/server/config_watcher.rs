@@ -0,0 +1,124 @@
+use crate::server::client::{Client, ClientResult, Running, Timeout};
+use crate::server::reloader::Reloader;
+
+use std::collections::BTreeSet;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures::future::FutureExt;
+use futures::select_biased;
+use glob::Pattern;
+use notify::{recommended_watcher, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+// Milliseconds to let an editor finish saving before reading the file
+const SETTLE_DELAY: u64 = 250;
+
+#[derive(Error, Debug)]
+pub enum WatchError {
+    #[error("Failed to watch the config files in {0}:\n{1}")]
+    NotifyError(String, notify::Error),
+}
+
+type WatchResult<T> = Result<T, WatchError>;
+
+// Reloads the config when any of its files change, or a file its includes
+// or the user's config name is made.
+// What's watched is settled on start, so includes added later
+// and directories that didn't exist yet are only seen after a restart.
+pub struct ConfigWatcher {
+    reloader: Reloader,
+    running: Running,
+    timeout: Timeout,
+    changes: mpsc::Receiver<()>,
+    // Stops watching when dropped
+    _watcher: RecommendedWatcher,
+}
+
+impl ConfigWatcher {
+    pub fn new(
+        paths: Vec<PathBuf>,
+        patterns: Vec<String>,
+        reloader: Reloader,
+        running: Running,
+        timeout: Timeout,
+    ) -> WatchResult<ConfigWatcher> {
+        // Events name files by the absolute path of their directory
+        let cwd = env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+        let files: Vec<PathBuf> = paths.iter().map(|p| cwd.join(p)).collect();
+        let patterns: Vec<PathBuf> = patterns.iter().map(|p| cwd.join(p)).collect();
+
+        // Editors often replace a file instead of writing to it,
+        // so the directories are watched for anything with their names.
+        // A glob's directory can only be watched if it isn't a glob too.
+        let dirs: BTreeSet<PathBuf> = files
+            .iter()
+            .chain(&patterns)
+            .map(|f| f.parent().unwrap_or_else(|| Path::new("/")).to_owned())
+            .filter(|d| d.is_dir())
+            .collect();
+
+        let patterns: Vec<Pattern> = patterns
+            .iter()
+            .filter_map(|p| Pattern::new(&p.to_string_lossy()).ok())
+            .collect();
+        let (sender, changes) = mpsc::channel(1);
+        let mut watcher = recommended_watcher(move |event: notify::Result<Event>| {
+            if let Ok(e) = event {
+                let is_config = e
+                    .paths
+                    .iter()
+                    .any(|p| files.contains(p) || patterns.iter().any(|g| g.matches_path(p)));
+                if is_config && !matches!(e.kind, EventKind::Access(_)) {
+                    // Full means a reload is already coming
+                    let _ = sender.try_send(());
+                }
+            }
+        })
+        .map_err(|e| WatchError::NotifyError("/".to_owned(), e))?;
+        for dir in dirs {
+            watcher
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .map_err(|e| WatchError::NotifyError(dir.to_string_lossy().into_owned(), e))?;
+        }
+
+        Ok(ConfigWatcher {
+            reloader,
+            running,
+            timeout,
+            changes,
+            _watcher: watcher,
+        })
+    }
+}
+
+#[async_trait]
+impl Client for ConfigWatcher {
+    async fn start(mut self) -> ClientResult<()> {
+        while self.running.load() {
+            select_biased! {
+                _ = self.timeout.wait().fuse() => {}
+                change = self.changes.recv().fuse() => {
+                    if change.is_none() {
+                        break;
+                    }
+                    sleep(Duration::from_millis(SETTLE_DELAY)).await;
+                    while self.changes.try_recv().is_ok() {}
+
+                    match self.reloader.reload().await {
+                        Ok(()) => println!("Reloaded the changed config"),
+                        Err(e) => println!("Kept the old config, the new one has an error:\n{}", e),
+                    }
+                }
+            }
+        }
+
+        println!("Watch End");
+        Ok(())
+    }
+}
@@ -0,0 +1,265 @@
+use crate::server::client::{
+    Activity, ActivitySender, Client, ClientResult, Command, Idle, Running, Score, Timeout, Usage,
+};
+use crate::server::reloader::Reloader;
+
+use std::collections::HashMap;
+use std::fs::remove_file;
+use std::io::{self, prelude::*, BufReader as StdBufReader};
+use std::os::unix::net::UnixStream as StdUnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+use chrono::Local;
+use futures::future::FutureExt;
+use futures::select_biased;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::oneshot;
+use tokio::time::sleep;
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+pub const CONTROL_SOCKET: &str = "septem.sock";
+
+#[derive(Error, Debug)]
+pub enum ControlError {
+    #[error("Failed to listen on the control socket {0}:\n{1}")]
+    BindError(String, io::Error),
+
+    #[error("{0}")]
+    AcceptError(#[from] io::Error),
+}
+
+type ControlResult<T> = Result<T, ControlError>;
+
+// One request per line, ie {"command": "status"}
+#[derive(Deserialize, Debug)]
+#[serde(tag = "command", rename_all = "lowercase")]
+enum Request {
+    Status,
+    // Resumes by itself after the seconds, if given
+    Pause { seconds: Option<u64> },
+    Resume,
+    Reload,
+    Flush,
+    Shutdown,
+    // Switches to the profile, or back to the schedule without a name
+    Profile { name: Option<String> },
+}
+
+// What every connection needs to answer requests
+#[derive(Clone)]
+struct State {
+    reloader: Reloader,
+    sender: ActivitySender,
+    running: Running,
+    timeout: Timeout,
+    score: Score,
+    idle: Idle,
+    paused: Running,
+    // Counts pauses and resumes so a timed pause only
+    // resumes if nothing else happened since
+    changes: Arc<AtomicU64>,
+    usage: Usage,
+}
+
+impl State {
+    // Today's totals with the time of the open span
+    fn status(&self) -> Value {
+        let now = Local::now();
+        let today = self.usage.load();
+        let mut totals: HashMap<String, u64> = if today.day == now.date_naive() {
+            today.totals.names
+        } else {
+            HashMap::new()
+        };
+
+        let (app, category) = match today.open {
+            Some((name, category, start)) => {
+                if start.date_naive() == now.date_naive() {
+                    *totals.entry(name.to_owned()).or_insert(0) +=
+                        (now - start).num_seconds().max(0) as u64;
+                }
+                (Some(name), Some(category))
+            }
+            None => (None, None),
+        };
+
+        let score = self.score.load();
+        json!({
+            "ok": true,
+            "app": app,
+            "category": category,
+            "score": score,
+            "productive": score > 0.0,
+            "idle": self.idle.load(),
+            "paused": self.paused.load(),
+            "profile": self.reloader.active(),
+            "chosen": self.reloader.chosen().is_some(),
+            "today": totals,
+        })
+    }
+
+    // Waits for the recorder to handle the command
+    async fn command(&self, command: Command) -> Result<(), String> {
+        let (reply, answer) = oneshot::channel();
+        self.sender
+            .send(Activity::Command(command, reply))
+            .await
+            .map_err(|_| "The recorder has stopped".to_owned())?;
+        answer
+            .await
+            .map_err(|_| "The recorder has stopped".to_owned())?
+    }
+
+    async fn pause(&self, seconds: Option<u64>) -> Result<(), String> {
+        self.command(Command::Pause).await?;
+        self.paused.store(true);
+        let change = self.changes.fetch_add(1, Ordering::SeqCst) + 1;
+
+        if let Some(s) = seconds {
+            let state = self.clone();
+            tokio::spawn(async move {
+                sleep(Duration::from_secs(s)).await;
+                if state.changes.load(Ordering::SeqCst) == change {
+                    let _ = state.resume().await;
+                }
+            });
+        }
+        Ok(())
+    }
+
+    async fn resume(&self) -> Result<(), String> {
+        self.command(Command::Resume).await?;
+        self.paused.store(false);
+        self.changes.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    async fn answer(&self, request: Request) -> Value {
+        let result = match request {
+            Request::Status => return self.status(),
+            Request::Pause { seconds } => self.pause(seconds).await,
+            Request::Resume => self.resume().await,
+            Request::Flush => self.command(Command::Flush).await,
+            Request::Reload => self.reloader.reload().await,
+            Request::Profile { name } => self.reloader.choose(name).await,
+            Request::Shutdown => {
+                self.running.store(false);
+                self.timeout.notify_all();
+                Ok(())
+            }
+        };
+
+        match result {
+            Ok(()) => json!({ "ok": true }),
+            Err(e) => json!({ "ok": false, "error": e }),
+        }
+    }
+}
+
+// Sends one request to the daemon listening in the runtime directory
+// and waits for its answer
+pub fn request(runtime: &Path, request: &Value) -> io::Result<Value> {
+    let mut stream = StdUnixStream::connect(runtime.join(CONTROL_SOCKET))?;
+    let mut line = request.to_string();
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+
+    let mut answer = String::new();
+    StdBufReader::new(stream).read_line(&mut answer)?;
+    Ok(serde_json::from_str(&answer)?)
+}
+
+// Lets status bars, scripts and the CLI talk to the daemon.
+// Every request line is answered with a line of JSON that has "ok"
+// and an "error" when it failed, status adds the current state:
+//   {"command": "status"} -> {"ok": true, "app": "code", "score": 1.0, ...}
+pub struct ControlListener {
+    state: State,
+    path: PathBuf,
+    listener: UnixListener,
+}
+
+impl ControlListener {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        runtime: PathBuf,
+        reloader: Reloader,
+        sender: ActivitySender,
+        running: Running,
+        timeout: Timeout,
+        score: Score,
+        idle: Idle,
+        usage: Usage,
+    ) -> ControlResult<ControlListener> {
+        let path = runtime.join(CONTROL_SOCKET);
+        let bind_error = |e| ControlError::BindError(path.to_string_lossy().into_owned(), e);
+
+        // Left behind if we weren't shut down cleanly
+        if path.exists() {
+            remove_file(&path).map_err(bind_error)?;
+        }
+        let listener = UnixListener::bind(&path).map_err(bind_error)?;
+
+        Ok(ControlListener {
+            state: State {
+                reloader,
+                sender,
+                running,
+                timeout,
+                score,
+                idle,
+                paused: Running::new(false),
+                changes: Arc::new(AtomicU64::new(0)),
+                usage,
+            },
+            path,
+            listener,
+        })
+    }
+
+    async fn serve(stream: UnixStream, state: State) {
+        let (read, mut write) = stream.into_split();
+        let mut lines = BufReader::new(read).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let reply = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => state.answer(request).await,
+                Err(e) => json!({ "ok": false, "error": e.to_string() }),
+            };
+
+            let mut reply = reply.to_string();
+            reply.push('\n');
+            if write.write_all(reply.as_bytes()).await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Client for ControlListener {
+    async fn start(self) -> ClientResult<()> {
+        while self.state.running.load() {
+            select_biased! {
+                _ = self.state.timeout.wait().fuse() => {}
+                conn = self.listener.accept().fuse() => {
+                    let (stream, _) = conn.map_err(ControlError::from)?;
+                    tokio::spawn(ControlListener::serve(stream, self.state.clone()));
+                }
+            }
+        }
+
+        let _ = remove_file(&self.path);
+        println!("Control End");
+        Ok(())
+    }
+}
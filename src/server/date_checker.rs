@@ -2,12 +2,17 @@ use crate::config::date_config::{
     Date::{MonthDay, MonthWeekDay},
     DateTimeConfig, Hours,
 };
-use crate::server::client::{Client, ClientResult, Running, Timeout};
+use crate::config::diagnostics::{first, KeyPath};
+use crate::server::client::{Client, ClientResult, Running, Shared, Timeout};
 
-use std::collections::HashSet;
 use std::time::Duration;
 
-use chrono::{Date, Datelike, Local, NaiveDate, NaiveTime, Timelike, Weekday};
+use chrono::{
+    Datelike, Duration as Days, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Weekday,
+};
+
+use futures::future::FutureExt;
+use futures::select_biased;
 
 use async_trait::async_trait;
 use thiserror::Error;
@@ -20,15 +25,17 @@ pub enum DateError {
     #[error("{0} doesn't have {2} number of {1}s")]
     WeekdayOfMonthError(&'static str, Weekday, u32),
 
-    #[error("{0} has its start time > stop time")]
-    FlipFlopTimeError(Weekday),
+    #[error("{0} has hours that stop when they start")]
+    EmptyTimeError(Weekday),
 
-    #[error("{0} was stated multiple times in config for start times")]
-    RepeatedWeekdayError(Weekday),
+    #[error("{0} has start hours that overlap")]
+    OverlapTimeError(Weekday),
 }
 
 type DateResult<T> = Result<T, DateError>;
 
+const WEEK: i64 = 7 * 24 * 3600;
+
 #[derive(Debug)]
 enum StartStopTimes {
     EndOfDay(Duration, bool),
@@ -37,93 +44,166 @@ enum StartStopTimes {
 }
 
 pub struct DateChecker {
-    config: DateTimeConfig,
+    config: Shared<DateTimeConfig>,
     running: Running,
     alerts_on: Running,
     timeout: Timeout,
+    // Notified when the config is reloaded
+    reloaded: Timeout,
 }
 
 impl DateChecker {
     pub fn new(
-        config: DateTimeConfig,
+        config: Shared<DateTimeConfig>,
         running: Running,
         alerts_on: Running,
         timeout: Timeout,
+        reloaded: Timeout,
     ) -> DateResult<DateChecker> {
-        Self::sanity_check(&config)?;
+        Self::sanity_check(&config.load())?;
         Ok(DateChecker {
             config,
             running,
             alerts_on,
             timeout,
+            reloaded,
         })
     }
 
-    fn sanity_check(config: &DateTimeConfig) -> DateResult<()> {
-        let opt_from_ymwd = NaiveDate::from_weekday_of_month_opt;
-        let opt_from_ymd = NaiveDate::from_ymd_opt;
-        let today = Local::today();
+    pub fn sanity_check(config: &DateTimeConfig) -> DateResult<()> {
+        first(Self::problems(config))
+    }
+
+    // Every problem with the config and where it is
+    pub fn problems(config: &DateTimeConfig) -> Vec<(KeyPath, DateError)> {
+        // A day off only has to exist in some year, ie Feb 29 or the 5th Monday
+        // of a month. The calendar repeats every 28 years, 2000 is a leap year.
+        let years = 2000..2028;
+        let exists_ymwd = |m, day, week| {
+            years
+                .clone()
+                .any(|y| NaiveDate::from_weekday_of_month_opt(y, m, day, week).is_some())
+        };
+        let exists_ymd = |m, day| {
+            years
+                .clone()
+                .any(|y| NaiveDate::from_ymd_opt(y, m, day).is_some())
+        };
+        let root = KeyPath::new().key("date_and_time");
+        let mut problems = Vec::new();
 
-        for date in config.dates() {
+        for (i, date) in config.dates().iter().enumerate() {
+            let path = root.key("disabled_days").index(i);
             match *date {
                 MonthWeekDay { month, week, day } => {
                     let m = month.number_from_month();
-                    if let None = opt_from_ymwd(today.year(), m, day, week as u8) {
-                        return Err(DateError::WeekdayOfMonthError(month.name(), day, week));
+                    if !exists_ymwd(m, day, week as u8) {
+                        let e = DateError::WeekdayOfMonthError(month.name(), day, week);
+                        problems.push((path.key("week"), e));
                     }
                 }
                 MonthDay { month, day } => {
                     let m = month.number_from_month();
-                    if let None = opt_from_ymd(today.year(), m, day) {
-                        return Err(DateError::DayOfMonthError(month.name(), day));
+                    if !exists_ymd(m, day) {
+                        let e = DateError::DayOfMonthError(month.name(), day);
+                        problems.push((path.key("day"), e));
                     }
                 }
             }
         }
 
-        let mut weekday_set = HashSet::new();
+        // A weekday can have many windows, ie around lunch, but they can't overlap,
+        // even with one from the day before that runs past midnight
+        let mut spans: Vec<(i64, i64)> = Vec::new();
 
-        for hours in config.start_hours() {
-            if !weekday_set.contains(&hours.weekday()) {
-                weekday_set.insert(hours.weekday().clone());
-            } else {
-                return Err(DateError::RepeatedWeekdayError(hours.weekday()));
+        for (i, hours) in config.start_hours().iter().enumerate() {
+            let path = root.key("start_hours").index(i);
+            if hours.start() == hours.stop() {
+                let e = DateError::EmptyTimeError(hours.weekday());
+                problems.push((path.key("stop"), e));
+                continue;
             }
 
-            if hours.start() >= hours.stop() {
-                return Err(DateError::FlipFlopTimeError(hours.weekday()));
+            let span = Self::week_span(hours);
+            let overlaps = spans.iter().any(|s| {
+                [-WEEK, 0, WEEK]
+                    .iter()
+                    .any(|w| span.0 < s.1 + w && s.0 + w < span.1)
+            });
+            if overlaps {
+                let e = DateError::OverlapTimeError(hours.weekday());
+                problems.push((path.key("start"), e));
             }
+            spans.push(span);
         }
 
-        Ok(())
+        problems
     }
 
-    fn weekdays_hours(&self, weekday: Weekday) -> (NaiveTime, NaiveTime) {
-        for hours in self.config.start_hours() {
-            if weekday == hours.weekday() {
-                return (hours.start(), hours.stop());
-            }
+    // Seconds from the start of Monday to the start and stop of the hours
+    fn week_span(hours: &Hours) -> (i64, i64) {
+        let day = hours.weekday().num_days_from_monday() as i64 * 24 * 3600;
+        let start = day + hours.start().num_seconds_from_midnight() as i64;
+        let mut stop = day + hours.stop().num_seconds_from_midnight() as i64;
+        if hours.stop() < hours.start() {
+            stop += 24 * 3600;
+        }
+        (start, stop)
+    }
+
+    // The weekday's windows, the whole day without any
+    fn weekdays_hours(config: &DateTimeConfig, weekday: Weekday) -> Vec<(NaiveTime, NaiveTime)> {
+        let mut windows: Vec<(NaiveTime, NaiveTime)> = config
+            .start_hours()
+            .iter()
+            .filter(|h| h.weekday() == weekday)
+            .map(|h| (h.start(), h.stop()))
+            .collect();
+
+        if windows.is_empty() {
+            let default = Hours::default();
+            windows.push((default.start(), default.stop()));
         }
+        windows
+    }
 
-        let default = Hours::default();
-        (default.start(), default.stop())
+    // The day's windows and the day before's that run past midnight, by start.
+    // A window belongs to the day it starts on, so that day's being disabled
+    // is what counts.
+    fn windows(config: &DateTimeConfig, today: NaiveDate) -> Vec<(NaiveDateTime, NaiveDateTime)> {
+        let mut windows = Vec::new();
+        for day in [today - Days::days(1), today] {
+            if !Self::should_run(config, &day) {
+                continue;
+            }
+            for (start, stop) in Self::weekdays_hours(config, day.weekday()) {
+                let mut end = day.and_time(stop);
+                if stop < start {
+                    end += Days::days(1);
+                }
+                windows.push((day.and_time(start), end));
+            }
+        }
+        windows.sort();
+        windows
     }
 
-    fn should_run(&self, date: &Date<Local>) -> bool {
-        let from_ymwd = NaiveDate::from_weekday_of_month;
-        let from_ymd = NaiveDate::from_ymd;
+    // Days off that don't exist in the date's year, ie Feb 29,
+    // don't disable anything that year
+    fn should_run(config: &DateTimeConfig, date: &NaiveDate) -> bool {
+        let from_ymwd = NaiveDate::from_weekday_of_month_opt;
+        let from_ymd = NaiveDate::from_ymd_opt;
 
-        for d in self.config.dates() {
+        for d in config.dates() {
             match *d {
                 MonthWeekDay { month, week, day } => {
-                    if date.naive_local()
-                        == from_ymwd(date.year(), month.number_from_month(), day, week as u8)
-                    {
+                    let m = month.number_from_month();
+                    if Some(*date) == from_ymwd(date.year(), m, day, week as u8) {
                         return false;
                     }
                 }
                 MonthDay { month, day } => {
-                    if date.naive_local() == from_ymd(date.year(), month.number_from_month(), day) {
+                    if Some(*date) == from_ymd(date.year(), month.number_from_month(), day) {
                         return false;
                     }
                 }
@@ -132,22 +212,17 @@ impl DateChecker {
         true
     }
 
-    fn next_time(&self) -> StartStopTimes {
-        let now = Local::now();
-        let weekday = now.weekday();
-        let (start, stop) = self.weekdays_hours(weekday);
-
-        let run_today = self.should_run(&now.date());
-
-        if run_today {
-            if now.time() < start {
-                let time = start - now.time();
+    fn next_time(config: &DateTimeConfig, now: NaiveDateTime) -> StartStopTimes {
+        // The window the time is in, or else the next one to start today
+        for (start, stop) in Self::windows(config, now.date()) {
+            if now < start {
+                let time = start - now;
                 return StartStopTimes::StartOfAlerts(Duration::from_secs(
                     time.num_seconds() as u64
                 ));
-            } else if now.time() >= start && now.time() < stop {
-                let time = stop - now.time();
-                if stop == NaiveTime::from_hms(23, 59, 59) {
+            } else if now < stop {
+                let time = stop - now;
+                if stop.time() == NaiveTime::from_hms(23, 59, 59) {
                     return StartStopTimes::EndOfDay(
                         Duration::from_secs(time.num_seconds() as u64),
                         true,
@@ -170,21 +245,204 @@ impl Client for DateChecker {
     async fn start(mut self) -> ClientResult<()> {
         use StartStopTimes::*;
         while self.running.load() {
-            match self.next_time() {
+            let now = Local::now().naive_local();
+            let d = match Self::next_time(&self.config.load(), now) {
                 StartOfAlerts(d) => {
                     self.alerts_on.store(false);
-                    self.timeout.wait_timeout(d).await?;
+                    d
                 }
                 EndOfAlerts(d) => {
                     self.alerts_on.store(true);
-                    self.timeout.wait_timeout(d).await?;
+                    d
                 }
                 EndOfDay(d, is_running) => {
                     self.alerts_on.store(is_running);
-                    self.timeout.wait_timeout(d).await?;
+                    d
                 }
+            };
+            // New hours or days off take effect right away
+            select_biased! {
+                r = self.timeout.wait_timeout(d).fuse() => r?,
+                _ = self.reloaded.wait().fuse() => {}
             }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(text: &str) -> DateTimeConfig {
+        toml::from_str(text).unwrap()
+    }
+
+    fn at(time: &str) -> NaiveDateTime {
+        time.parse().unwrap()
+    }
+
+    // Weekdays without hours run all day
+    const NIGHT_SHIFT: &str = r#"
+        start_hours = [
+            {weekday = "Fri", start = "22:00:00", stop = "06:00:00"},
+            {weekday = "Sat", start = "12:00:00", stop = "15:00:00"},
+        ]
+    "#;
+
+    #[test]
+    fn overnight_window_runs_into_the_next_day() {
+        let conf = config(NIGHT_SHIFT);
+        assert!(matches!(
+            DateChecker::next_time(&conf, at("2026-10-17T03:00:00")),
+            StartStopTimes::EndOfAlerts(d) if d == Duration::from_secs(3 * 3600)
+        ));
+        assert!(matches!(
+            DateChecker::next_time(&conf, at("2026-10-16T21:00:00")),
+            StartStopTimes::StartOfAlerts(d) if d == Duration::from_secs(3600)
+        ));
+    }
+
+    #[test]
+    fn overnight_window_is_off_when_the_day_it_starts_is_disabled() {
+        let conf = config(&format!(
+            "disabled_days = [{{month = \"Oct\", day = 16}}]\n{}",
+            NIGHT_SHIFT
+        ));
+        assert_eq!(
+            DateChecker::windows(&conf, at("2026-10-17T03:00:00").date()),
+            vec![(at("2026-10-17T12:00:00"), at("2026-10-17T15:00:00"))]
+        );
+        assert!(matches!(
+            DateChecker::next_time(&conf, at("2026-10-17T03:00:00")),
+            StartStopTimes::StartOfAlerts(d) if d == Duration::from_secs(9 * 3600)
+        ));
+    }
+
+    #[test]
+    fn day_without_hours_runs_until_the_end_of_the_day() {
+        let conf = config("");
+        assert!(matches!(
+            DateChecker::next_time(&conf, at("2026-10-16T12:00:00")),
+            StartStopTimes::EndOfDay(d, true) if d == Duration::from_secs(12 * 3600 - 1)
+        ));
+    }
+
+    #[test]
+    fn days_off_missing_from_last_year_are_skipped() {
+        let conf = config(
+            r#"disabled_days = [
+                {month = "Feb", day = 29},
+                {month = "Dec", week = 5, day = "Thu"},
+            ]"#,
+        );
+        assert!(DateChecker::should_run(
+            &conf,
+            &at("2025-12-31T00:00:00").date()
+        ));
+        assert_eq!(
+            DateChecker::windows(&conf, at("2026-01-01T12:00:00").date()).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn days_off_only_have_to_exist_in_some_year() {
+        let conf = config(
+            r#"disabled_days = [
+                {month = "Feb", day = 29},
+                {month = "Feb", week = 5, day = "Sun"},
+                {month = "Feb", day = 30},
+                {month = "Jun", week = 5, day = "Sat"},
+                {month = "Feb", week = 6, day = "Mon"},
+            ]"#,
+        );
+        let problems: Vec<String> = DateChecker::problems(&conf)
+            .iter()
+            .map(|(path, e)| format!("{}: {}", path, e))
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "date_and_time.disabled_days[2].day: February doesn't have 30 number of days",
+                "date_and_time.disabled_days[4].week: February doesn't have 6 number of Mons",
+            ]
+        );
+    }
+
+    fn hours(weekday: &str, start: &str, stop: &str) -> Hours {
+        toml::from_str(&format!(
+            "weekday = \"{}\"\nstart = \"{}\"\nstop = \"{}\"",
+            weekday, start, stop
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn week_span_counts_from_monday_and_runs_past_midnight() {
+        assert_eq!(
+            DateChecker::week_span(&hours("Tue", "09:00:00", "17:00:00")),
+            (24 * 3600 + 9 * 3600, 24 * 3600 + 17 * 3600)
+        );
+        assert_eq!(
+            DateChecker::week_span(&hours("Sun", "22:00:00", "06:00:00")),
+            (6 * 24 * 3600 + 22 * 3600, 7 * 24 * 3600 + 6 * 3600)
+        );
+    }
+
+    #[test]
+    fn windows_around_lunch_are_allowed() {
+        let conf = config(
+            r#"start_hours = [
+                {weekday = "Mon", start = "09:30:00", stop = "12:00:00"},
+                {weekday = "Mon", start = "13:00:00", stop = "17:00:00"},
+                {weekday = "Sun", start = "22:00:00", stop = "06:00:00"},
+                {weekday = "Mon", start = "06:00:00", stop = "09:00:00"},
+            ]"#,
+        );
+        assert!(DateChecker::problems(&conf).is_empty());
+
+        // Off over lunch until the afternoon starts
+        assert!(matches!(
+            DateChecker::next_time(&conf, at("2026-10-19T12:30:00")),
+            StartStopTimes::StartOfAlerts(d) if d == Duration::from_secs(1800)
+        ));
+    }
+
+    #[test]
+    fn overlapping_windows_are_reported() {
+        let conf = config(
+            r#"start_hours = [
+                {weekday = "Mon", start = "09:00:00", stop = "12:00:00"},
+                {weekday = "Mon", start = "11:00:00", stop = "17:00:00"},
+                {weekday = "Mon", start = "17:00:00", stop = "17:00:00"},
+            ]"#,
+        );
+        let problems = DateChecker::problems(&conf);
+        assert_eq!(problems.len(), 2);
+        assert!(matches!(
+            problems[0].1,
+            DateError::OverlapTimeError(Weekday::Mon)
+        ));
+        assert!(matches!(
+            problems[1].1,
+            DateError::EmptyTimeError(Weekday::Mon)
+        ));
+    }
+
+    #[test]
+    fn sunday_night_overlapping_monday_morning_is_reported() {
+        let conf = config(
+            r#"start_hours = [
+                {weekday = "Mon", start = "05:00:00", stop = "09:00:00"},
+                {weekday = "Sun", start = "22:00:00", stop = "06:00:00"},
+            ]"#,
+        );
+        let problems = DateChecker::problems(&conf);
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(
+            problems[0].1,
+            DateError::OverlapTimeError(Weekday::Sun)
+        ));
+    }
+}
@@ -1,4 +1,6 @@
-use crate::server::client::{Client, ClientResult, PidSender, Running};
+use crate::server::client::{
+    ActiveWindow, Activity, ActivitySender, Client, ClientResult, Running,
+};
 
 use xcb::{ConnError, GenericError};
 use xcb_util::ewmh;
@@ -32,7 +34,7 @@ impl From<GenericError> for EventError {
 type EventResult<T> = Result<T, EventError>;
 
 pub struct EventHandler {
-    sender: PidSender,
+    sender: ActivitySender,
     running: Running,
     conn: ewmh::Connection,
     window: xcb::Window,
@@ -40,6 +42,7 @@ pub struct EventHandler {
     active_win: u32,
     wm_name: u32,
     vis_name: u32,
+    watched: xcb::Window,
 }
 
 unsafe impl Send for EventHandler {}
@@ -71,7 +74,80 @@ impl EventHandler {
         self.window
     }
 
-    pub fn new(sender: PidSender, running: Running) -> EventResult<EventHandler> {
+    fn active_window(&self) -> EventResult<u32> {
+        Ok(ewmh::get_active_window(&self.conn, self.screen_id).get_reply()?)
+    }
+
+    fn pid(&self, active: xcb::Window) -> EventResult<u32> {
+        Ok(ewmh::get_wm_pid(&self.conn, active).get_reply()?)
+    }
+
+    // Title changes are only sent to the window's own listeners,
+    // so the active window has to be watched as well as the root.
+    // The one watched before is let go so background windows stay quiet.
+    fn watch(&mut self, active: xcb::Window) {
+        if active == self.watched || active == xcb::NONE {
+            return;
+        }
+
+        if self.watched != xcb::NONE {
+            let list = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_NO_EVENT)];
+            xcb::change_window_attributes(&self.conn, self.watched, &list);
+        }
+        let list = [(xcb::CW_EVENT_MASK, xcb::EVENT_MASK_PROPERTY_CHANGE)];
+        xcb::change_window_attributes(&self.conn, active, &list);
+        self.conn.flush();
+        self.watched = active;
+    }
+
+    // WM_CLASS holds the instance and class names as two
+    // null terminated strings
+    fn class(&self, active: xcb::Window) -> (String, String) {
+        let cookie = xcb::get_property(
+            &self.conn,
+            false,
+            active,
+            xcb::ATOM_WM_CLASS,
+            xcb::ATOM_STRING,
+            0,
+            1024,
+        );
+        match cookie.get_reply() {
+            Ok(class) => {
+                let mut names = class
+                    .value::<u8>()
+                    .split(|c| *c == 0)
+                    .map(|n| String::from_utf8_lossy(n).into_owned());
+                let instance = names.next().unwrap_or_default();
+                let class = names.next().unwrap_or_default();
+                (instance, class)
+            }
+            Err(_) => (String::new(), String::new()),
+        }
+    }
+
+    // UTF8 _NET_WM_NAME falling back to the legacy WM_NAME
+    fn title(&self, active: xcb::Window) -> String {
+        if let Ok(name) = ewmh::get_wm_name(&self.conn, active).get_reply() {
+            return name.string().to_owned();
+        }
+
+        let cookie = xcb::get_property(
+            &self.conn,
+            false,
+            active,
+            xcb::ATOM_WM_NAME,
+            xcb::ATOM_ANY,
+            0,
+            1024,
+        );
+        match cookie.get_reply() {
+            Ok(name) => String::from_utf8_lossy(name.value::<u8>()).into_owned(),
+            Err(_) => String::new(),
+        }
+    }
+
+    pub fn new(sender: ActivitySender, running: Running) -> EventResult<EventHandler> {
         let (conn, screen_id, window) = Self::establish_conn()?;
 
         let active_win = conn.ACTIVE_WINDOW();
@@ -87,6 +163,7 @@ impl EventHandler {
             active_win,
             wm_name,
             vis_name,
+            watched: xcb::NONE,
         })
     }
 }
@@ -94,35 +171,41 @@ impl EventHandler {
 #[async_trait]
 impl Client for EventHandler {
     async fn start(mut self) -> ClientResult<()> {
-        let get_aw = |conn, id| -> EventResult<u32> {
-            Ok(xcb_util::ewmh::get_active_window(conn, id).get_reply()?)
-        };
-
-        let get_pid = |conn, aw| -> EventResult<u32> {
-            Ok(xcb_util::ewmh::get_wm_pid(conn, aw).get_reply()?)
-        };
-
-        let has_error = || -> EventResult<()> {
-            self.conn.has_error()?;
-            Ok(())
-        };
-
         while self.running.load() {
             let event_option = self.conn.wait_for_event();
             if let Some(event) = event_option {
                 let e = event.response_type() & !0x80;
                 if e == xcb::PROPERTY_NOTIFY {
                     let prop: &xcb::PropertyNotifyEvent = unsafe { xcb::cast_event(&event) };
+                    // Events from a window that was active before can still be queued
+                    let w = prop.window();
+                    if w != self.window && w != self.watched {
+                        continue;
+                    }
                     let a = prop.atom();
-                    if a == self.active_win || a == self.wm_name || a == self.vis_name {
-                        let active = get_aw(&self.conn, self.screen_id)?;
-                        let pid = if active == xcb::NONE {
+                    let is_name = a == self.wm_name || a == self.vis_name || a == xcb::ATOM_WM_NAME;
+                    if w == self.watched && is_name {
+                        // Still the same window, only its title changed
+                        let title = self.title(w);
+                        if self.sender.send(Activity::Title(title)).await.is_err() {
+                            return Err(EventError::PidSenderError.into());
+                        }
+                    } else if a == self.active_win {
+                        let active = self.active_window()?;
+                        let window = if active == xcb::NONE {
                             None
                         } else {
-                            Some(get_pid(&self.conn, active)?)
+                            let (instance, class) = self.class(active);
+                            Some(ActiveWindow {
+                                pid: self.pid(active)?,
+                                title: self.title(active),
+                                instance,
+                                class,
+                            })
                         };
+                        self.watch(active);
 
-                        if let Err(_) = &self.sender.send(pid).await {
+                        if let Err(_) = &self.sender.send(Activity::Focus(window)).await {
                             return Err(EventError::PidSenderError.into());
                         }
                     }
@@ -132,7 +215,7 @@ impl Client for EventHandler {
                     if client.type_() == xcb::ATOM_ANY {
                         if client.format() == 32 {
                             if client.data().data32().eq(&[0; 5]) {
-                                if let Err(_) = &self.sender.send(None).await {
+                                if let Err(_) = &self.sender.send(Activity::Focus(None)).await {
                                     return Err(EventError::PidSenderError.into());
                                 }
                                 break;
@@ -141,7 +224,7 @@ impl Client for EventHandler {
                     }
                 }
             } else {
-                has_error()?;
+                self.conn.has_error().map_err(EventError::from)?;
             }
         }
         println!("Event End");
@@ -0,0 +1,145 @@
+use crate::config::diagnostics::{first, KeyPath};
+use crate::config::idle_config::IdleConfig;
+use crate::server::client::{Activity, ActivitySender, Client, ClientResult, Idle, Running};
+
+use std::time::Duration;
+use tokio::time::sleep;
+
+use chrono::Local;
+use xcb::{screensaver, ConnError, GenericError};
+
+use async_trait::async_trait;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum IdleError {
+    #[error("Connection to the X11 server failed to start or stopped running")]
+    ConnectionError(#[from] ConnError),
+
+    #[error("Failed to find screen with id: {0}")]
+    ScreenIteratorError(i32),
+
+    #[error("The X11 server doesn't support the MIT-SCREEN-SAVER extension")]
+    MissingExtensionError,
+
+    #[error("Failed to query the screen saver idle counter\nError Code is {1}")]
+    GenericXcbError(GenericError, u8),
+
+    #[error("The idle threshold must be greater than 0 seconds")]
+    ZeroThresholdError,
+
+    #[error("Activity Recv must have closed")]
+    ActivitySenderError,
+}
+
+impl From<GenericError> for IdleError {
+    fn from(error: GenericError) -> Self {
+        let code = error.error_code();
+        IdleError::GenericXcbError(error, code)
+    }
+}
+
+type IdleResult<T> = Result<T, IdleError>;
+
+pub struct IdleWatcher {
+    sender: ActivitySender,
+    running: Running,
+    idle: Idle,
+    config: IdleConfig,
+    conn: xcb::Connection,
+    root: xcb::Window,
+}
+
+unsafe impl Send for IdleWatcher {}
+unsafe impl Sync for IdleWatcher {}
+
+impl IdleWatcher {
+    fn establish_conn() -> IdleResult<(xcb::Connection, xcb::Window)> {
+        let (conn, screen_id) = xcb::Connection::connect(None)?;
+        conn.has_error()?;
+
+        if conn.get_extension_data(screensaver::id()).is_none() {
+            return Err(IdleError::MissingExtensionError);
+        }
+
+        let root = conn
+            .get_setup()
+            .roots()
+            .nth(screen_id as usize)
+            .ok_or(IdleError::ScreenIteratorError(screen_id))?
+            .root();
+
+        Ok((conn, root))
+    }
+
+    pub fn sanity_check(config: &IdleConfig) -> IdleResult<()> {
+        first(IdleWatcher::problems(config))
+    }
+
+    pub fn problems(config: &IdleConfig) -> Vec<(KeyPath, IdleError)> {
+        let mut problems = Vec::new();
+        if config.threshold() == 0 {
+            let path = KeyPath::new().key("idle").key("threshold");
+            problems.push((path, IdleError::ZeroThresholdError));
+        }
+        problems
+    }
+
+    pub fn new(
+        config: IdleConfig,
+        sender: ActivitySender,
+        running: Running,
+        idle: Idle,
+    ) -> IdleResult<IdleWatcher> {
+        IdleWatcher::sanity_check(&config)?;
+        let (conn, root) = Self::establish_conn()?;
+
+        Ok(IdleWatcher {
+            sender,
+            running,
+            idle,
+            config,
+            conn,
+            root,
+        })
+    }
+
+    // Milliseconds since the last keyboard or mouse input
+    fn idle_time(&self) -> IdleResult<u32> {
+        let info = screensaver::query_info(&self.conn, self.root).get_reply()?;
+        Ok(info.ms_since_user_input())
+    }
+}
+
+#[async_trait]
+impl Client for IdleWatcher {
+    async fn start(self) -> ClientResult<()> {
+        let threshold = self.config.threshold() * 1000;
+
+        while self.running.load() {
+            sleep(Duration::from_millis(self.config.delay())).await;
+
+            let idle_time = self.idle_time()?;
+            let is_idle = idle_time as u64 >= threshold;
+            if is_idle == self.idle.load() {
+                continue;
+            }
+
+            self.idle.store(is_idle);
+            let activity = if is_idle {
+                Activity::Idle(Local::now() - chrono::Duration::milliseconds(idle_time as i64))
+            } else {
+                Activity::Resume
+            };
+
+            if self.sender.send(activity).await.is_err() {
+                // The recorder stops listening once we are shutting down
+                if self.running.load() {
+                    return Err(IdleError::ActivitySenderError.into());
+                }
+            }
+        }
+        println!("Idle End");
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use crate::config::diagnostics::KeyPath;
+use crate::config::profile_config::{Profiles, DEFAULT_PROFILE};
+use crate::server::client::{Client, ClientResult, Running, Timeout};
+use crate::server::reloader::Reloader;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use async_trait::async_trait;
+use chrono::Local;
+use futures::{select_biased, FutureExt};
+use thiserror::Error;
+
+// Remembers the profile chosen by hand across restarts
+const PROFILE_FILE: &str = "profile";
+
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("There's no profile named {0}")]
+    UnknownProfileError(String),
+
+    #[error("The {0} profile is the top level settings and can't be defined")]
+    ReservedNameError(String),
+
+    #[error("A block of the {0} profile has its start >= stop")]
+    FlipFlopTimeError(String),
+}
+
+pub fn problems(profiles: &Profiles) -> Vec<(KeyPath, ProfileError)> {
+    let mut problems = Vec::new();
+    if profiles.named().contains_key(DEFAULT_PROFILE) {
+        let path = KeyPath::new().key("profiles").key(DEFAULT_PROFILE);
+        let e = ProfileError::ReservedNameError(DEFAULT_PROFILE.to_owned());
+        problems.push((path, e));
+    }
+
+    for (i, block) in profiles.schedule().iter().enumerate() {
+        let path = KeyPath::new().key("schedule").index(i);
+        let name = block.profile().to_owned();
+        if profiles.get(&name).is_none() {
+            problems.push((path.key("profile"), ProfileError::UnknownProfileError(name)));
+        } else if block.stop().is_some_and(|s| block.start() >= s) {
+            problems.push((path.key("stop"), ProfileError::FlipFlopTimeError(name)));
+        }
+    }
+    problems
+}
+
+// The profile last chosen by hand, unless it was cleared
+pub fn remembered(state: &Path) -> Option<String> {
+    let name = fs::read_to_string(state.join(PROFILE_FILE)).ok()?;
+    let name = name.trim();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_owned())
+    }
+}
+
+pub fn remember(state: &Path, chosen: Option<&str>) -> io::Result<()> {
+    let path = state.join(PROFILE_FILE);
+    match chosen {
+        Some(name) => fs::write(path, format!("{}\n", name)),
+        None if path.exists() => fs::remove_file(path),
+        None => Ok(()),
+    }
+}
+
+// Switches to the scheduled profile when a block starts or ends,
+// unless one was chosen by hand
+pub struct ProfileScheduler {
+    reloader: Reloader,
+    running: Running,
+    timeout: Timeout,
+    // Notified when the config is reloaded
+    reloaded: Timeout,
+}
+
+impl ProfileScheduler {
+    pub fn new(
+        reloader: Reloader,
+        running: Running,
+        timeout: Timeout,
+        reloaded: Timeout,
+    ) -> ProfileScheduler {
+        ProfileScheduler {
+            reloader,
+            running,
+            timeout,
+            reloaded,
+        }
+    }
+}
+
+#[async_trait]
+impl Client for ProfileScheduler {
+    async fn start(self) -> ClientResult<()> {
+        while self.running.load() {
+            // Sleeps until the next block starts or stops,
+            // a new schedule takes effect right away
+            let now = Local::now().naive_local();
+            let next = self.reloader.profiles().next_boundary(now);
+            let until = (next - now).to_std().unwrap_or_default();
+            select_biased! {
+                r = self.timeout.wait_timeout(until).fuse() => r?,
+                _ = self.reloaded.wait().fuse() => {}
+            }
+
+            if let Err(e) = self.reloader.follow_schedule().await {
+                println!("Failed to switch to the scheduled profile:\n{}", e);
+            }
+        }
+        Ok(())
+    }
+}
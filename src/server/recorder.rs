@@ -1,171 +1,686 @@
+mod classifier;
 mod process;
-use process::{Process, ProcessError};
-
-use crate::config::recorder_config::RecorderConfig;
-use crate::server::client::{Client, ClientResult, PidRecv, Productive, Running};
+mod report;
+mod span;
+mod storage;
+use classifier::{Classifier, Subject};
+use process::{convert_name, name_from_cmdline, Process, ProcessError};
+use report::Report;
+pub use report::{hms, week_of};
+use span::Span;
+use storage::{ProcTimes, Storage};
+
+use crate::config::{
+    budget_config::Budget,
+    diagnostics::{first, KeyPath},
+    recorder_config::{Identity, ProcessName, RecorderConfig},
+};
+use crate::server::client::{
+    ActiveWindow, Activity, ActivityRecv, Client, ClientResult, Command, DayTotals, Reply, Running,
+    Score, Today, Usage,
+};
 
 use tokio::task::JoinError;
 
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::prelude::*;
+use std::collections::{BTreeMap, HashSet};
 use std::path::Path;
-use std::time::SystemTime;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
-use csv::{ReaderBuilder, WriterBuilder};
-use serde::{Deserialize, Serialize};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, Weekday};
+use futures::{future::FutureExt, select_biased};
 use thiserror::Error;
-
-const DATA_FILE: &str = "data.csv";
+use tokio::time::{sleep_until, Instant};
 
 #[derive(Error, Debug)]
 pub enum RecorderError {
     #[error("Recorder error getting the new process:\n{0}")]
     GetProcessError(#[from] ProcessError),
 
+    #[error("The weight of {0} is {1}, it must be from -1.0 to 1.0")]
+    WeightRangeError(String, f64),
+
+    #[error("{0} was stated multiple times in config for weekday productive lists")]
+    RepeatedWeekdayError(Weekday),
+
+    #[error("The pattern {0} failed to compile:\n{1}")]
+    PatternError(String, String),
+
+    #[error("The rule for {0} uses category {1} that doesn't exist")]
+    UnknownCategoryError(String, String),
+
+    #[error("The rule for {0} has its start time >= stop time")]
+    FlipFlopTimeError(String),
+
     #[error("Share path {0} does not exist")]
     PathDoesNotExistError(String),
 
     #[error("{0}")]
     CsvError(#[from] csv::Error),
 
+    #[error("{0}")]
+    SqliteError(#[from] rusqlite::Error),
+
+    #[error("The data in {0} is corrupt: {1}")]
+    CorruptDataError(String, String),
+
+    #[error("Database schema version {0} isn't the supported version {1}, an older one is upgraded when the daemon starts")]
+    SchemaVersionError(u32, u32),
+
+    #[error("The stored names were read with process_name = \"{0}\", {2} can't be converted to \"{1}\".\nSet process_name back or move the data aside to start over")]
+    ProcessNameError(&'static str, &'static str, String),
+
     #[error("{0}")]
     FileError(#[from] std::io::Error),
 
     #[error("{0}")]
     WriteThreadError(#[from] JoinError),
 
-    #[error("Pid channel closed")]
-    PidChannelError,
+    #[error("Activity channel closed")]
+    ActivityChannelError,
 }
 
 pub type RecorderResult<T> = Result<T, RecorderError>;
 
-// Data type to be broken up as Recorder's hashmap
-// and stored within the data file
-#[derive(Debug, Deserialize, Serialize)]
-struct Data {
-    name: String,
-    time: u64,
-    is_prod: bool,
+// Prints the totals of the days from the stored spans,
+// without needing the daemon to be running
+pub fn print_report(
+    share: &Path,
+    conf: &RecorderConfig,
+    budgets: &[Budget],
+    from: NaiveDate,
+    to: NaiveDate,
+) -> RecorderResult<()> {
+    let storage = storage::open_read_only(conf.storage(), share)?;
+
+    let mut proc_times = ProcTimes::new();
+    let mut days: BTreeMap<NaiveDate, DayTotals> = BTreeMap::new();
+    for span in storage.spans_since(from)? {
+        let day = span.start.date_naive();
+        if day > to {
+            continue;
+        }
+        proc_times
+            .entry(span.name.to_owned())
+            .or_default()
+            .add(span.secs(), span.score);
+        days.entry(day)
+            .or_default()
+            .add(&span.name, &span.category, span.secs());
+    }
+
+    Report::range(from, to, &proc_times)
+        .budgets(budgets, &days)
+        .write_to(std::io::stdout().lock())?;
+    Ok(())
+}
+
+// The key Focus::new makes with the config's process name strategy,
+// from one it made when the names were read another way.
+// Classes and domains stay, None when a process name can't be converted.
+fn convert_key(key: &str, conf: &RecorderConfig, from: ProcessName) -> Option<String> {
+    let convert = |name: &str| convert_name(name, from, conf.process_name());
+    let (app, rest) = match key.split_once(':') {
+        Some((a, r)) => (a, Some(r)),
+        None => (key, None),
+    };
+    let is_listed = |list: &Vec<String>| {
+        list.iter()
+            .any(|l| *l == app || app.split_once('/').is_some_and(|(c, p)| *l == c || *l == p))
+    };
+
+    let mut new = match (conf.identity(), app.split_once('/')) {
+        (Identity::Both, Some((class, process))) if !class.is_empty() => {
+            format!("{}/{}", class, convert(process)?)
+        }
+        // A window without a class is named by its process, but there's no telling
+        (Identity::Class, _) => app.to_owned(),
+        _ => convert(app)?,
+    };
+
+    // A terminal's job comes before a browser's domain
+    if let Some(rest) = rest {
+        let rest = match (is_listed(conf.terminals()), is_listed(conf.browsers())) {
+            (true, true) => match rest.split_once(':') {
+                Some((job, domain)) => format!("{}:{}", convert(job)?, domain),
+                None => rest.to_owned(),
+            },
+            (true, false) => convert(rest)?,
+            (false, _) => rest.to_owned(),
+        };
+        new = format!("{}:{}", new, rest);
+    }
+    Some(new)
+}
+
+// The focused window and the process that owns it
+#[derive(Clone, Debug)]
+struct Focus {
+    process: Process,
+    job: Option<Process>,
+    domain: Option<String>,
+    window: ActiveWindow,
+    key: String,
+    category: String,
+    score: f64,
+}
+
+impl Focus {
+    fn new(
+        window: ActiveWindow,
+        domain: Option<&str>,
+        config: &RecorderConfig,
+        classifier: &Classifier,
+    ) -> RecorderResult<Focus> {
+        let process = Process::new(window.pid as i32, config.process_name())?;
+        let is_listed = |list: &Vec<String>| {
+            list.iter()
+                .any(|l| *l == process.name || *l == window.class || *l == window.instance)
+        };
+
+        let is_terminal = is_listed(config.terminals());
+        let job = if is_terminal {
+            process.foreground(config.process_name())
+        } else {
+            None
+        };
+
+        // Windows without a class fall back to their process' name
+        let mut key = match (config.identity(), window.class.is_empty()) {
+            (Identity::Process, _) | (_, true) => process.name.to_owned(),
+            (Identity::Class, false) => window.class.to_owned(),
+            (Identity::Both, false) => format!("{}/{}", window.class, process.name),
+        };
+        // Terminal time goes to the job, ie alacritty:nvim
+        if let Some(j) = &job {
+            key = format!("{}:{}", key, j.name);
+        }
+
+        // and browser time to the tab, ie firefox:docs.rs
+        let domain = if is_listed(config.browsers()) {
+            domain.map(str::to_owned)
+        } else {
+            None
+        };
+
+        if let Some(d) = &domain {
+            key = format!("{}:{}", key, d);
+        }
+
+        let mut focus = Focus {
+            process,
+            job,
+            domain,
+            window,
+            key,
+            category: String::new(),
+            score: 0.0,
+        };
+        focus.classify(config, classifier, Local::now().naive_local());
+        Ok(focus)
+    }
+
+    // Rules and productive lists can depend on the time,
+    // true if the category or score changed
+    fn classify(
+        &mut self,
+        config: &RecorderConfig,
+        classifier: &Classifier,
+        now: NaiveDateTime,
+    ) -> bool {
+        let subject = match &self.domain {
+            // The tab decides, not the browser
+            Some(d) => Subject {
+                names: vec![self.key.as_str(), d.as_str()],
+                classes: Vec::new(),
+                title: &self.window.title,
+            },
+            None => {
+                let mut names = vec![self.key.as_str(), self.process.name.as_str()];
+                if let Some(j) = &self.job {
+                    names.push(j.name.as_str());
+                }
+                Subject {
+                    names,
+                    classes: vec![self.window.class.as_str(), self.window.instance.as_str()],
+                    title: &self.window.title,
+                }
+            }
+        };
+        let (category, score) = classifier.classify(config, &subject, now);
+
+        let changed = category != self.category || score != self.score;
+        self.category = category;
+        self.score = score;
+        changed
+    }
+
+    // Same as classify after the window's title changed
+    fn retitle(
+        &mut self,
+        title: String,
+        config: &RecorderConfig,
+        classifier: &Classifier,
+        now: NaiveDateTime,
+    ) -> bool {
+        self.window.title = title;
+        self.classify(config, classifier, now)
+    }
 }
 
 pub struct Recorder {
-    recv: PidRecv,
+    recv: ActivityRecv,
     running: Running,
-    is_prod: Productive,
+    score: Score,
     config: RecorderConfig,
+    classifier: Classifier,
+    budgets: Vec<Budget>,
+    // Spans are tagged with it
+    profile: String,
+    usage: Usage,
     share_dir: String,
-    prev_proc: Option<Process>,
-    curr_proc: Option<Process>,
-    start_time: SystemTime,
+    storage: Arc<dyn Storage>,
+    curr: Option<Focus>,
+    domain: Option<String>,
+    idle: bool,
+    // Stopped from the control socket
+    paused: bool,
+    // Answered once the next write is done
+    flushed: Option<Reply>,
+    start_time: DateTime<Local>,
     write_time: SystemTime,
-    proc_times: HashMap<String, (u64, bool)>,
+    week: NaiveDate,
+    spans: Vec<Span>,
+    proc_times: ProcTimes,
+    // This week's totals of each day, for budgets
+    days: BTreeMap<NaiveDate, DayTotals>,
 }
 
 impl Recorder {
     // Procedural Functions
 
-    fn add_data(&mut self, data: Data) {
-        match self.proc_times.get_mut(&data.name) {
-            Some((t, p)) => {
-                *t += data.time;
-                *p = data.is_prod;
-            }
-            None => {
-                self.proc_times.insert(data.name, (data.time, data.is_prod));
-            }
+    // Time isn't recorded while idle or paused
+    fn recording(&self) -> bool {
+        !self.idle && !self.paused
+    }
+
+    // Totals are derived from spans, which are kept until the next write
+    fn add_span(&mut self, span: Span) {
+        self.days.entry(span.start.date_naive()).or_default().add(
+            &span.name,
+            &span.category,
+            span.secs(),
+        );
+        self.proc_times
+            .entry(span.name.to_owned())
+            .or_default()
+            .add(span.secs(), span.score);
+        self.spans.push(span);
+    }
+
+    // The focused process' span up to the given time.
+    // Nothing is recorded for the time spent idle.
+    fn open_span(&self, end: DateTime<Local>) -> Option<Span> {
+        let f = self.curr.as_ref()?;
+        if !self.recording() {
+            return None;
         }
+        Some(Span {
+            start: self.start_time,
+            end,
+            pid: f.job.as_ref().unwrap_or(&f.process).pid,
+            name: f.key.to_owned(),
+            class: f.window.class.to_owned(),
+            title: f.window.title.to_owned(),
+            category: f.category.to_owned(),
+            score: f.score,
+            profile: self.profile.to_owned(),
+        })
     }
 
-    fn create_data(path: &Path) -> RecorderResult<()> {
-        if path.is_dir() {
-            let data = path.join(DATA_FILE);
-            let mut f = File::create(data)?;
-            f.write_all(b"name,time,is_prod")?;
-            Ok(())
-        } else {
-            Err(RecorderError::PathDoesNotExistError(
-                path.to_string_lossy().into_owned(),
-            ))
+    // Ends the focused process' span at the given time
+    fn close_span(&mut self, end: DateTime<Local>) {
+        if let Some(span) = self.open_span(end) {
+            self.add_span(span);
         }
+        self.start_time = end;
     }
 
-    fn parse_data(
-        share: &str,
-        productive: &[String],
-    ) -> RecorderResult<HashMap<String, (u64, bool)>> {
-        let path = Path::new(share);
-        let data = path.join(DATA_FILE);
+    fn this_week() -> NaiveDate {
+        week_of(Local::now().date_naive())
+    }
+
+    // Freezes the totals of the finished week into a report
+    // and starts a fresh accumulator for the current one
+    fn rollover(&mut self) -> RecorderResult<()> {
+        let week = Recorder::this_week();
+        if week == self.week {
+            return Ok(());
+        }
+
+        let days = self.days.split_off(&week);
+        let past_days = std::mem::replace(&mut self.days, days);
+
+        if !self.proc_times.is_empty() {
+            let report =
+                Report::new(self.week, &self.proc_times).budgets(&self.budgets, &past_days);
+            let path = report.write(Path::new(&self.share_dir))?;
+            println!(
+                "Week of {} was {:.2}% productive with a score of {:.2}%, report written to {}",
+                self.week,
+                report.productive_percent(),
+                report.score_percent(),
+                path.display()
+            );
+        }
+
+        self.proc_times.clear();
+        self.week = week;
+        Ok(())
+    }
 
-        if data.exists() {
-            let reader = ReaderBuilder::new().from_path(data)?;
-            let mut map = HashMap::new();
-            for r in reader.into_deserialize() {
-                let data: Data = r?;
-                let prod = productive.contains(&data.name);
-                map.insert(data.name, (data.time, prod));
+    // Every problem with the config, its rules included, and where it is
+    pub fn problems(conf: &RecorderConfig) -> Vec<(KeyPath, RecorderError)> {
+        let in_range = |w: f64| (-1.0..=1.0).contains(&w);
+        let root = KeyPath::new().key("recorder");
+        let mut problems = Vec::new();
+
+        for (i, c) in conf.categories().iter().enumerate() {
+            if !in_range(c.weight()) {
+                let e = RecorderError::WeightRangeError(c.name().to_owned(), c.weight());
+                problems.push((root.key("categories").index(i).key("weight"), e));
+            }
+        }
+
+        let mut weekday_set = HashSet::new();
+        for (i, day) in conf.weekdays().iter().enumerate() {
+            if !weekday_set.insert(day.weekday()) {
+                let e = RecorderError::RepeatedWeekdayError(day.weekday());
+                problems.push((root.key("weekdays").index(i).key("weekday"), e));
             }
-            Ok(map)
-        } else {
-            Recorder::create_data(path)?;
-            Ok(HashMap::new())
         }
+
+        if !in_range(conf.default_weight()) {
+            let e =
+                RecorderError::WeightRangeError("default_weight".to_owned(), conf.default_weight());
+            problems.push((root.key("default_weight"), e));
+        }
+
+        problems.extend(Classifier::problems(conf));
+        problems
+    }
+
+    // Checks the config and compiles its rules
+    pub fn classifier(conf: &RecorderConfig) -> RecorderResult<Classifier> {
+        first(Recorder::problems(conf))?;
+        Classifier::new(conf)
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         share: String,
         conf: RecorderConfig,
-        recv: PidRecv,
+        budgets: Vec<Budget>,
+        profile: String,
+        recv: ActivityRecv,
         running: Running,
-        is_prod: Productive,
+        score: Score,
+        usage: Usage,
     ) -> RecorderResult<Recorder> {
-        let map = Recorder::parse_data(&share, conf.productive())?;
+        let classifier = Recorder::classifier(&conf)?;
+        let storage = storage::open(conf.storage(), Path::new(&share))?;
+
+        // Names read another way are converted to how they're read now
+        let strategy = conf.process_name();
+        let stored = storage.process_name()?.unwrap_or(strategy);
+        if stored != strategy {
+            let is_raw = |name: &str| name.contains('\0');
+            for name in storage.names()? {
+                if !is_raw(&name) && convert_key(&name, &conf, stored).is_none() {
+                    return Err(RecorderError::ProcessNameError(
+                        stored.name(),
+                        strategy.name(),
+                        name,
+                    ));
+                }
+            }
+            let renamed = storage.rename(&|name| {
+                if is_raw(name) {
+                    return None;
+                }
+                convert_key(name, &conf, stored).filter(|n| n != name)
+            })?;
+            println!(
+                "Converted {} names read with process_name = \"{}\" to \"{}\"",
+                renamed,
+                stored.name(),
+                strategy.name()
+            );
+        }
+        storage.set_process_name(strategy)?;
+
+        // Names used to be the raw cmdline with its null separators
+        let renamed = storage.rename(&|name| {
+            if name.contains('\0') {
+                Some(name_from_cmdline(name, strategy))
+            } else {
+                None
+            }
+        })?;
+        if renamed > 0 {
+            println!("Migrated {} names recorded from raw cmdlines", renamed);
+        }
+
+        // Stored totals are kept as they were scored when they were recorded
+        let (week, map) = storage.load()?;
 
-        Ok(Recorder {
+        let mut recorder = Recorder {
             recv,
             running,
-            is_prod,
+            score,
             config: conf,
+            classifier,
+            budgets,
+            profile,
+            usage,
             share_dir: share,
-            prev_proc: None,
-            curr_proc: None,
-            start_time: SystemTime::now(),
+            storage,
+            curr: None,
+            domain: None,
+            idle: false,
+            paused: false,
+            flushed: None,
+            start_time: Local::now(),
             write_time: SystemTime::now(),
+            week,
+            spans: Vec::new(),
             proc_times: map,
-        })
+            days: BTreeMap::new(),
+        };
+
+        // Budgets count from the totals already stored for the week
+        for span in recorder.storage.spans_since(week)? {
+            recorder
+                .days
+                .entry(span.start.date_naive())
+                .or_default()
+                .add(&span.name, &span.category, span.secs());
+        }
+
+        // Data left over from a week that ended while we weren't running
+        recorder.rollover()?;
+        Ok(recorder)
     }
 
     // Async Functions
 
     async fn write_data(
-        share: String,
-        proc_times: HashMap<String, (u64, bool)>,
+        storage: Arc<dyn Storage>,
+        spans: Vec<Span>,
+        proc_times: ProcTimes,
+        open: Option<Span>,
     ) -> RecorderResult<()> {
-        let mut writer = WriterBuilder::new().from_path(Path::new(&share).join(DATA_FILE))?;
-        for (name, (time, is_prod)) in proc_times.into_iter() {
-            writer.serialize(Data {
-                name,
-                time,
-                is_prod,
-            })?;
+        storage.write(&spans, &proc_times, open.as_ref())
+    }
+
+    // Config that can't be checked or compiled is rejected,
+    // the shared keys like storage and process_name only change on restart
+    fn reload(
+        &mut self,
+        conf: RecorderConfig,
+        budgets: Vec<Budget>,
+        profile: String,
+    ) -> RecorderResult<()> {
+        let now = Local::now();
+        let conf = conf.with_shared(&self.config);
+        self.classifier = Recorder::classifier(&conf)?;
+        self.config = conf;
+        self.budgets = budgets;
+
+        // The open span ends with the profile it was recorded under
+        if profile != self.profile {
+            self.close_span(now);
+            self.profile = profile;
+            if let Some(f) = self.curr.as_mut() {
+                f.classify(&self.config, &self.classifier, now.naive_local());
+            }
+        } else {
+            self.reclassify(now);
         }
         Ok(())
     }
 
+    fn command(&mut self, command: Command, reply: Reply, now: DateTime<Local>) {
+        let result = match command {
+            Command::Pause => {
+                self.close_span(now);
+                self.paused = true;
+                Ok(())
+            }
+            Command::Resume => {
+                if self.paused {
+                    self.paused = false;
+                    self.start_time = now;
+                }
+                Ok(())
+            }
+            Command::Flush => {
+                self.close_span(now);
+                self.flushed = Some(reply);
+                return;
+            }
+            Command::Reload(conf, budgets, profile) => self
+                .reload(*conf, budgets, profile)
+                .map_err(|e| e.to_string()),
+        };
+        // The client may have hung up
+        let _ = reply.send(result);
+    }
+
+    // Lets the budget checker see today's totals and the open span
+    fn publish_usage(&self) {
+        let day = Local::now().date_naive();
+        let open = match (&self.curr, self.recording()) {
+            (Some(f), true) => Some((f.key.to_owned(), f.category.to_owned(), self.start_time)),
+            _ => None,
+        };
+        self.usage.store(Today {
+            day,
+            totals: self.days.get(&day).cloned().unwrap_or_default(),
+            open,
+        });
+    }
+
+    // Starts a new span when the focus' classification changes with the time,
+    // or at midnight so each day gets its own spans
+    fn reclassify(&mut self, now: DateTime<Local>) {
+        if let Some(mut f) = self.curr.clone() {
+            let changed = f.classify(&self.config, &self.classifier, now.naive_local());
+            if changed || now.date_naive() != self.start_time.date_naive() {
+                self.close_span(now);
+                self.curr = Some(f);
+            }
+        }
+    }
+
     async fn wait_for_event(&mut self) -> RecorderResult<()> {
-        let get_proc = |u| -> RecorderResult<Process> { Ok(Process::new(u as i32)?) };
-        self.prev_proc = self.curr_proc.clone();
-        self.curr_proc = match self.recv.recv().await {
-            Some(pid) => match pid {
-                Some(u) => Some(get_proc(u)?),
-                None => None,
+        let now = Local::now().naive_local();
+        let boundary = self.classifier.next_boundary(now);
+        let until = (boundary - now).to_std().unwrap_or_default();
+        // The open span is written at least as often as write_delay,
+        // without closing it
+        let checkpoint = self.write_time + Duration::from_secs(self.config.write_delay().max(1));
+        let until_checkpoint = checkpoint
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+
+        let activity = select_biased! {
+            a = self.recv.recv().fuse() => match a {
+                Some(a) => a,
+                None => {
+                    return Err(RecorderError::ActivityChannelError);
+                }
             },
-            None => {
-                return Err(RecorderError::PidChannelError);
+            _ = sleep_until(Instant::now() + until).fuse() => {
+                self.reclassify(Local::now());
+                return Ok(());
+            }
+            _ = sleep_until(Instant::now() + until_checkpoint).fuse() => {
+                return Ok(());
             }
         };
+
+        let now = Local::now();
+        match activity {
+            Activity::Focus(window) => {
+                self.close_span(now);
+                self.curr = match window {
+                    Some(w) => Some(Focus::new(
+                        w,
+                        self.domain.as_deref(),
+                        &self.config,
+                        &self.classifier,
+                    )?),
+                    None => None,
+                };
+            }
+            // The span only ends when the title changes what it's classified as,
+            // otherwise it goes on with the new title
+            Activity::Title(title) => {
+                if let Some(mut f) = self.curr.clone() {
+                    if f.retitle(title, &self.config, &self.classifier, now.naive_local()) {
+                        self.close_span(now);
+                    }
+                    self.curr = Some(f);
+                }
+            }
+            Activity::Domain(domain) => {
+                self.domain = domain;
+                if let Some(f) = self.curr.clone() {
+                    // Only a browser's focus depends on the domain
+                    let focus = Focus::new(
+                        f.window,
+                        self.domain.as_deref(),
+                        &self.config,
+                        &self.classifier,
+                    )?;
+                    if focus.domain != f.domain {
+                        self.close_span(now);
+                        self.curr = Some(focus);
+                    }
+                }
+            }
+            Activity::Idle(since) => {
+                self.close_span(since.max(self.start_time));
+                self.idle = true;
+            }
+            Activity::Resume => {
+                self.idle = false;
+                self.start_time = now;
+            }
+            Activity::Command(command, reply) => self.command(command, reply, now),
+        }
         Ok(())
     }
 }
@@ -174,56 +689,194 @@ impl Recorder {
 impl Client for Recorder {
     async fn start(mut self) -> ClientResult<()> {
         let mut write_handle = tokio::spawn(Recorder::write_data(
-            self.share_dir.to_owned(),
+            self.storage.clone(),
+            Vec::new(),
             self.proc_times.to_owned(),
+            None,
         ));
         self.write_time = SystemTime::now();
 
         while self.running.load() {
             let error = self.wait_for_event().await;
             if let Err(e) = error {
-                if let RecorderError::PidChannelError = e {
+                if let RecorderError::ActivityChannelError = e {
                     break;
                 } else {
                     return Err(e.into());
                 }
             }
 
-            if let Some(p) = self.curr_proc.clone() {
-                self.is_prod
-                    .store(self.config.productive().contains(&p.name));
-            } else {
-                self.is_prod.store(false);
-            }
-
-            if let Some(p) = self.prev_proc.clone() {
-                self.add_data(Data {
-                    is_prod: self.config.productive().contains(&p.name),
-                    name: p.name,
-                    time: self.start_time.elapsed().unwrap().as_secs(),
-                });
-
-                let write_elapsed = self.write_time.elapsed().unwrap().as_secs();
-
-                if write_elapsed >= self.config.write_delay() {
-                    write_handle.await??;
-                    write_handle = tokio::spawn(Recorder::write_data(
-                        self.share_dir.to_owned(),
-                        self.proc_times.to_owned(),
-                    ));
-                    self.write_time = SystemTime::now();
-                }
+            let score = match (&self.curr, self.paused) {
+                (Some(f), false) => f.score,
+                _ => 0.0,
+            };
+            self.score.store(score);
+            self.publish_usage();
+
+            let week = self.week;
+            self.rollover()?;
+
+            let write_elapsed = self.write_time.elapsed().unwrap().as_secs();
+
+            if week != self.week
+                || write_elapsed >= self.config.write_delay()
+                || self.flushed.is_some()
+            {
+                write_handle.await??;
+                write_handle = tokio::spawn(Recorder::write_data(
+                    self.storage.clone(),
+                    self.spans.split_off(0),
+                    self.proc_times.to_owned(),
+                    self.open_span(Local::now()),
+                ));
+                self.write_time = SystemTime::now();
             }
 
-            for (proc, (time, prod)) in &self.proc_times {
-                println!("{}, {}, {}", proc, time, prod);
+            // A flush is only answered once it's on disk
+            if let Some(reply) = self.flushed.take() {
+                let written = write_handle.await?;
+                let _ = reply.send(written.as_ref().map_err(|e| e.to_string()).copied());
+                written?;
+                write_handle = tokio::spawn(async { Ok(()) });
             }
-
-            self.start_time = SystemTime::now();
         }
 
         write_handle.await??;
+        self.close_span(Local::now());
+        Recorder::write_data(
+            self.storage.clone(),
+            self.spans.split_off(0),
+            self.proc_times.to_owned(),
+            None,
+        )
+        .await?;
         println!("Rec End");
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(text: &str) -> RecorderConfig {
+        toml::from_str(&format!("write_delay = 20\n{}", text)).unwrap()
+    }
+
+    #[test]
+    fn process_keys_are_converted() {
+        let conf = config("");
+        assert_eq!(
+            convert_key("/usr/bin/nvim notes.md", &conf, ProcessName::Cmdline).unwrap(),
+            "nvim"
+        );
+        assert_eq!(convert_key("nvim", &conf, ProcessName::Comm), None);
+    }
+
+    #[test]
+    fn class_keys_keep_the_class() {
+        let conf = config(r#"identity = "both""#);
+        assert_eq!(
+            convert_key(
+                "Slack//usr/lib/slack/slack --silent",
+                &conf,
+                ProcessName::Cmdline
+            )
+            .unwrap(),
+            "Slack/slack"
+        );
+        // A window without a class
+        assert_eq!(
+            convert_key("/usr/bin/mpv", &conf, ProcessName::Cmdline).unwrap(),
+            "mpv"
+        );
+
+        let conf = config(r#"identity = "class""#);
+        assert_eq!(
+            convert_key("Slack", &conf, ProcessName::Comm).unwrap(),
+            "Slack"
+        );
+    }
+
+    #[test]
+    fn terminal_jobs_are_converted() {
+        let conf = config(r#"terminals = ["alacritty"]"#);
+        assert_eq!(
+            convert_key(
+                "alacritty:/usr/bin/nvim notes.md",
+                &conf,
+                ProcessName::Cmdline
+            )
+            .unwrap(),
+            "alacritty:nvim"
+        );
+        assert_eq!(
+            convert_key("alacritty:nvim", &conf, ProcessName::Comm),
+            None
+        );
+    }
+
+    #[test]
+    fn browser_domains_are_kept() {
+        let conf = config(
+            r#"
+            process_name = "comm"
+            terminals = ["kitty"]
+            browsers = ["firefox", "kitty"]
+            "#,
+        );
+        assert_eq!(
+            convert_key("firefox:docs.rs", &conf, ProcessName::Exe).unwrap(),
+            "firefox:docs.rs"
+        );
+        assert_eq!(
+            convert_key("kitty:w3m:docs.rs", &conf, ProcessName::Argv0).unwrap(),
+            "kitty:w3m:docs.rs"
+        );
+        // A terminal that's also a browser with no job
+        assert_eq!(
+            convert_key("kitty:docs.rs", &conf, ProcessName::Argv0).unwrap(),
+            "kitty:docs.rs"
+        );
+    }
+
+    #[test]
+    fn only_titles_that_change_the_category_split_the_span() {
+        let conf = config(
+            r#"
+            [[rules]]
+            match = "glob"
+            field = "title"
+            pattern = "*Invoice*"
+            category = "productive"
+            "#,
+        );
+        let classifier = Classifier::new(&conf).unwrap();
+        let now = "2026-10-19T09:00:00".parse().unwrap();
+        let mut focus = Focus {
+            process: Process {
+                pid: 1,
+                name: "thunderbird".to_owned(),
+            },
+            job: None,
+            domain: None,
+            window: ActiveWindow {
+                pid: 1,
+                title: "Inbox".to_owned(),
+                instance: "Mail".to_owned(),
+                class: "thunderbird".to_owned(),
+            },
+            key: "thunderbird".to_owned(),
+            category: String::new(),
+            score: 0.0,
+        };
+        focus.classify(&conf, &classifier, now);
+
+        let mut retitle = |title: &str| focus.retitle(title.to_owned(), &conf, &classifier, now);
+        assert!(!retitle("Inbox (1)"));
+        assert!(retitle("Re: Invoice 42"));
+        assert!(!retitle("Fwd: Invoice 42"));
+        assert!(retitle("Inbox"));
+        assert_eq!(focus.window.title, "Inbox");
+    }
+}
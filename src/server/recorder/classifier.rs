@@ -0,0 +1,352 @@
+use crate::config::diagnostics::KeyPath;
+use crate::config::recorder_config::{MatchKind, RecorderConfig, Rule, RuleField};
+use crate::server::recorder::{RecorderError, RecorderResult};
+
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+use glob::Pattern;
+use regex::Regex;
+
+// The names of a window a rule or category can match
+pub struct Subject<'a> {
+    pub names: Vec<&'a str>,
+    pub classes: Vec<&'a str>,
+    pub title: &'a str,
+}
+
+enum Matcher {
+    Exact(String),
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn matches(&self, s: &str) -> bool {
+        match self {
+            Matcher::Exact(e) => e == s,
+            Matcher::Glob(g) => g.matches(s),
+            Matcher::Regex(r) => r.is_match(s),
+        }
+    }
+}
+
+struct CompiledRule {
+    field: RuleField,
+    matcher: Matcher,
+    category: String,
+    weight: f64,
+    weekdays: Vec<Weekday>,
+    start: NaiveTime,
+    stop: Option<NaiveTime>,
+}
+
+impl CompiledRule {
+    fn applies(&self, now: NaiveDateTime) -> bool {
+        (self.weekdays.is_empty() || self.weekdays.contains(&now.weekday()))
+            && now.time() >= self.start
+            && self.stop.is_none_or(|s| now.time() < s)
+    }
+
+    fn matches(&self, subject: &Subject) -> bool {
+        match self.field {
+            RuleField::Process => subject.names.iter().any(|n| self.matcher.matches(n)),
+            RuleField::Class => subject.classes.iter().any(|c| self.matcher.matches(c)),
+            RuleField::Title => self.matcher.matches(subject.title),
+        }
+    }
+}
+
+// The config's rules with their patterns compiled,
+// falling back to the categories and productive lists
+pub struct Classifier {
+    rules: Vec<CompiledRule>,
+}
+
+impl Classifier {
+    // Errors come with the key of the rule that's wrong
+    fn compile(
+        config: &RecorderConfig,
+        rule: &Rule,
+    ) -> Result<CompiledRule, (&'static str, RecorderError)> {
+        let pattern = rule.pattern();
+        let compile_error = |e: String| {
+            (
+                "pattern",
+                RecorderError::PatternError(pattern.to_owned(), e),
+            )
+        };
+        let matcher = match rule.kind() {
+            MatchKind::Exact => Matcher::Exact(pattern.to_owned()),
+            MatchKind::Glob => {
+                Matcher::Glob(Pattern::new(pattern).map_err(|e| compile_error(e.to_string()))?)
+            }
+            MatchKind::Regex => {
+                Matcher::Regex(Regex::new(pattern).map_err(|e| compile_error(e.to_string()))?)
+            }
+        };
+
+        let weight = match rule.weight() {
+            Some(w) => w,
+            None => config.category_weight(rule.category()).ok_or_else(|| {
+                let e = RecorderError::UnknownCategoryError(
+                    pattern.to_owned(),
+                    rule.category().to_owned(),
+                );
+                ("category", e)
+            })?,
+        };
+        if !(-1.0..=1.0).contains(&weight) {
+            let e = RecorderError::WeightRangeError(pattern.to_owned(), weight);
+            return Err(("weight", e));
+        }
+        if rule.stop().is_some_and(|s| rule.start() >= s) {
+            return Err(("stop", RecorderError::FlipFlopTimeError(pattern.to_owned())));
+        }
+
+        Ok(CompiledRule {
+            field: rule.field(),
+            matcher,
+            category: rule.category().to_owned(),
+            weight,
+            weekdays: rule.weekdays().to_owned(),
+            start: rule.start(),
+            stop: rule.stop(),
+        })
+    }
+
+    pub fn new(config: &RecorderConfig) -> RecorderResult<Classifier> {
+        let rules = config
+            .rules()
+            .iter()
+            .map(|r| Classifier::compile(config, r).map_err(|(_, e)| e))
+            .collect::<RecorderResult<_>>()?;
+        Ok(Classifier { rules })
+    }
+
+    // Every rule that doesn't compile and where it's wrong
+    pub fn problems(config: &RecorderConfig) -> Vec<(KeyPath, RecorderError)> {
+        let path = KeyPath::new().key("recorder").key("rules");
+        config
+            .rules()
+            .iter()
+            .enumerate()
+            .filter_map(|(i, r)| {
+                Classifier::compile(config, r)
+                    .err()
+                    .map(|(k, e)| (path.index(i).key(k), e))
+            })
+            .collect()
+    }
+
+    pub fn classify(
+        &self,
+        config: &RecorderConfig,
+        subject: &Subject,
+        now: NaiveDateTime,
+    ) -> (String, f64) {
+        let rule = self
+            .rules
+            .iter()
+            .find(|r| r.applies(now) && r.matches(subject));
+        if let Some(r) = rule {
+            return (r.category.to_owned(), r.weight);
+        }
+
+        let names: Vec<&str> = subject
+            .names
+            .iter()
+            .chain(subject.classes.iter())
+            .copied()
+            .collect();
+        let (category, weight) = config.classify(&names, subject.title, now.weekday());
+        (category.to_owned(), weight)
+    }
+
+    // The next time a rule starts or stops applying, at the latest midnight
+    // when the weekday and its productive lists change
+    pub fn next_boundary(&self, now: NaiveDateTime) -> NaiveDateTime {
+        let today = now.date();
+        let midnight = today.and_time(NaiveTime::MIN) + Duration::days(1);
+        self.rules
+            .iter()
+            .flat_map(|r| [Some(r.start), r.stop])
+            .flatten()
+            .map(|t| today.and_time(t))
+            .filter(|t| *t > now)
+            .fold(midnight, NaiveDateTime::min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> NaiveDateTime {
+        time.parse().unwrap()
+    }
+
+    fn classifier() -> (RecorderConfig, Classifier) {
+        let config: RecorderConfig = toml::from_str(
+            r#"
+            write_delay = 20
+            [[rules]]
+            pattern = "Slack"
+            field = "class"
+            category = "productive"
+            weekdays = ["Mon", "Tue", "Wed", "Thu", "Fri"]
+            start = "09:00:00"
+            stop = "17:00:00"
+
+            [[rules]]
+            pattern = "mpv"
+            category = "uncategorized"
+            start = "20:00:00"
+            "#,
+        )
+        .unwrap();
+        let classifier = Classifier::new(&config).unwrap();
+        (config, classifier)
+    }
+
+    fn slack(classifier: &Classifier, config: &RecorderConfig, now: &str) -> String {
+        let subject = Subject {
+            names: vec!["slack"],
+            classes: vec!["Slack"],
+            title: "",
+        };
+        classifier.classify(config, &subject, at(now)).0
+    }
+
+    #[test]
+    fn rules_only_apply_during_their_hours() {
+        let (config, classifier) = classifier();
+        // 2026-10-19 is a Monday
+        assert_eq!(
+            slack(&classifier, &config, "2026-10-19T09:00:00"),
+            "productive"
+        );
+        assert_eq!(
+            slack(&classifier, &config, "2026-10-19T08:59:59"),
+            "uncategorized"
+        );
+        assert_eq!(
+            slack(&classifier, &config, "2026-10-19T17:00:00"),
+            "uncategorized"
+        );
+        assert_eq!(
+            slack(&classifier, &config, "2026-10-18T12:00:00"),
+            "uncategorized"
+        );
+    }
+
+    #[test]
+    fn next_boundary_is_the_next_start_or_stop_or_midnight() {
+        let (_, classifier) = classifier();
+        let next = |now| classifier.next_boundary(at(now));
+        assert_eq!(next("2026-10-19T08:00:00"), at("2026-10-19T09:00:00"));
+        assert_eq!(next("2026-10-19T09:00:00"), at("2026-10-19T17:00:00"));
+        assert_eq!(next("2026-10-19T18:00:00"), at("2026-10-19T20:00:00"));
+        assert_eq!(next("2026-10-19T21:00:00"), at("2026-10-20T00:00:00"));
+    }
+
+    fn config(text: &str) -> RecorderConfig {
+        toml::from_str(&format!("write_delay = 20\n{}", text)).unwrap()
+    }
+
+    fn category(config: &RecorderConfig, names: Vec<&str>, title: &str) -> String {
+        let subject = Subject {
+            names,
+            classes: Vec::new(),
+            title,
+        };
+        Classifier::new(config)
+            .unwrap()
+            .classify(config, &subject, at("2026-10-19T12:00:00"))
+            .0
+    }
+
+    #[test]
+    fn globs_and_regexes_match_names_and_titles() {
+        let config = config(
+            r#"
+            [[rules]]
+            match = "glob"
+            pattern = "firefox:*.rs"
+            category = "productive"
+
+            [[rules]]
+            match = "regex"
+            field = "title"
+            pattern = "^\\[[0-9]+\\] Inbox"
+            category = "productive"
+            "#,
+        );
+        assert_eq!(category(&config, vec!["firefox:docs.rs"], ""), "productive");
+        assert_eq!(
+            category(&config, vec!["firefox:youtube.com"], ""),
+            "uncategorized"
+        );
+        assert_eq!(
+            category(&config, vec!["thunderbird"], "[3] Inbox - Mail"),
+            "productive"
+        );
+        assert_eq!(
+            category(&config, vec!["thunderbird"], "Drafts [3] Inbox"),
+            "uncategorized"
+        );
+        // A title rule doesn't match names
+        assert_eq!(category(&config, vec!["[3] Inbox"], ""), "uncategorized");
+    }
+
+    #[test]
+    fn problems_point_at_the_key_of_the_rule() {
+        let config = config(
+            r#"
+            [[rules]]
+            match = "glob"
+            pattern = "firefox:[*.rs"
+            category = "productive"
+
+            [[rules]]
+            match = "regex"
+            pattern = "(slack"
+            category = "productive"
+
+            [[rules]]
+            pattern = "slack"
+            category = "productive"
+            weight = 2.0
+
+            [[rules]]
+            pattern = "mpv"
+            category = "uncategorized"
+            start = "20:00:00"
+            stop = "08:00:00"
+
+            [[rules]]
+            pattern = "mpv"
+            category = "games"
+            "#,
+        );
+        let problems: Vec<String> = Classifier::problems(&config)
+            .iter()
+            .map(|(path, e)| match e {
+                RecorderError::PatternError(..) => format!("{} pattern", path),
+                RecorderError::WeightRangeError(..) => format!("{} weight", path),
+                RecorderError::FlipFlopTimeError(..) => format!("{} flip flop", path),
+                RecorderError::UnknownCategoryError(..) => format!("{} category", path),
+                e => format!("{} {}", path, e),
+            })
+            .collect();
+        assert_eq!(
+            problems,
+            vec![
+                "recorder.rules[0].pattern pattern",
+                "recorder.rules[1].pattern pattern",
+                "recorder.rules[2].weight weight",
+                "recorder.rules[3].stop flip flop",
+                "recorder.rules[4].category category",
+            ]
+        );
+        assert!(Classifier::new(&config).is_err());
+    }
+}
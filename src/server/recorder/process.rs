@@ -7,8 +7,11 @@ extern "C" {
     fn proc_name(name_ptr: *mut *mut c_char, pid: pid_t) -> c_int;
 }
 
+use crate::config::recorder_config::ProcessName;
+
 use std::ffi::CStr;
-use std::fs::read_to_string;
+use std::fs::{read_dir, read_link, read_to_string};
+use std::path::Path;
 use std::str::Utf8Error;
 
 use thiserror::Error;
@@ -40,9 +43,127 @@ pub struct Process {
 
 unsafe impl Send for Process {}
 
+// The kernel truncates comm to 15 bytes
+const COMM_LEN: usize = 15;
+
+fn basename(path: &str) -> &str {
+    Path::new(path)
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or(path)
+}
+
+// Turns a raw, null separated cmdline into the name the strategy would
+// have read. Used to migrate keys recorded before there was a strategy.
+pub fn name_from_cmdline(cmdline: &str, strategy: ProcessName) -> String {
+    let mut args = cmdline.split('\0').filter(|a| !a.is_empty());
+    let argv0 = basename(args.next().unwrap_or_default()).to_owned();
+
+    match strategy {
+        ProcessName::Cmdline => cmdline
+            .split('\0')
+            .filter(|a| !a.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "),
+        ProcessName::Comm => argv0.chars().take(COMM_LEN).collect(),
+        ProcessName::Exe | ProcessName::Argv0 => argv0,
+    }
+}
+
+// The name one strategy reads from the name another one read.
+// None when the other one didn't keep enough of it, comm is cut short
+// and only cmdline has the arguments.
+pub fn convert_name(name: &str, from: ProcessName, to: ProcessName) -> Option<String> {
+    if from == to {
+        return Some(name.to_owned());
+    }
+
+    let program = match from {
+        ProcessName::Cmdline => basename(name.split(' ').next().unwrap_or_default()),
+        ProcessName::Exe | ProcessName::Argv0 => name,
+        ProcessName::Comm => return None,
+    };
+    match to {
+        ProcessName::Comm => Some(program.chars().take(COMM_LEN).collect()),
+        ProcessName::Exe | ProcessName::Argv0 => Some(program.to_owned()),
+        ProcessName::Cmdline => None,
+    }
+}
+
+// The fields of /proc/<pid>/stat needed to find a terminal's foreground job
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq)]
+struct Stat {
+    pid: pid_t,
+    ppid: pid_t,
+    pgrp: pid_t,
+    tpgid: pid_t,
+    // Clock ticks after boot
+    starttime: u64,
+}
+
+#[cfg(target_os = "linux")]
+impl Stat {
+    fn read(p: pid_t) -> Option<Stat> {
+        Stat::parse(p, &read_to_string(format!("/proc/{}/stat", p)).ok()?)
+    }
+
+    fn parse(p: pid_t, stat: &str) -> Option<Stat> {
+        // comm is in parentheses and can itself contain spaces or parentheses
+        // and is followed by the state
+        let fields: Vec<&str> = stat
+            .get(stat.rfind(')')? + 2..)?
+            .split_whitespace()
+            .collect();
+        let field = |n: usize| fields.get(n - 3)?.parse().ok();
+
+        Some(Stat {
+            pid: p,
+            ppid: field(4)?,
+            pgrp: field(5)?,
+            tpgid: field(8)?,
+            starttime: fields.get(22 - 3)?.parse().ok()?,
+        })
+    }
+
+    // The foreground group's leader, or any process left in the group
+    // when it's exited. A job is started by the shell, so it's one of its own.
+    fn job(&self) -> Option<Stat> {
+        if let Some(leader) = Stat::read(self.tpgid).filter(|s| s.pgrp == self.tpgid) {
+            return Some(leader);
+        }
+        let mut pids = children(self.pid);
+        while let Some(pid) = pids.pop() {
+            match Stat::read(pid) {
+                Some(s) if s.pgrp == self.tpgid => return Some(s),
+                Some(s) => pids.extend(children(s.pid)),
+                None => {}
+            }
+        }
+        None
+    }
+}
+
+// The children of every thread of the process
+#[cfg(target_os = "linux")]
+fn children(p: pid_t) -> Vec<pid_t> {
+    let tasks = match read_dir(format!("/proc/{}/task", p)) {
+        Ok(t) => t,
+        Err(_) => return Vec::new(),
+    };
+    tasks
+        .filter_map(|t| read_to_string(t.ok()?.path().join("children")).ok())
+        .flat_map(|c| {
+            c.split_whitespace()
+                .filter_map(|pid| pid.parse().ok())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
 impl Process {
     #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
-    pub fn new(p: pid_t) -> Result<Process, ProcessError> {
+    pub fn new(p: pid_t, _strategy: ProcessName) -> Result<Process, ProcessError> {
         let mut proc = Process {
             pid: p,
             name: String::new(),
@@ -63,10 +184,125 @@ impl Process {
     }
 
     #[cfg(target_os = "linux")]
-    pub fn new(p: pid_t) -> Result<Process, ProcessError> {
-        Ok(Process {
-            pid: p,
-            name: read_to_string(format!("/proc/{}/cmdline", p))?,
-        })
+    pub fn new(p: pid_t, strategy: ProcessName) -> Result<Process, ProcessError> {
+        let name = match strategy {
+            ProcessName::Comm => read_to_string(format!("/proc/{}/comm", p))?
+                .trim_end()
+                .to_owned(),
+            ProcessName::Exe => {
+                let exe = read_link(format!("/proc/{}/exe", p))?;
+                let exe = exe.to_string_lossy();
+                basename(exe.trim_end_matches(" (deleted)")).to_owned()
+            }
+            ProcessName::Argv0 | ProcessName::Cmdline => {
+                name_from_cmdline(&read_to_string(format!("/proc/{}/cmdline", p))?, strategy)
+            }
+        };
+
+        Ok(Process { pid: p, name })
+    }
+
+    // The job in the foreground of a terminal emulator's pty.
+    // The shells are the emulator's children and know the pty's
+    // foreground process group. With tabs the job started last
+    // is taken as the one being used.
+    #[cfg(target_os = "linux")]
+    pub fn foreground(&self, strategy: ProcessName) -> Option<Process> {
+        let job = children(self.pid)
+            .into_iter()
+            .filter_map(Stat::read)
+            .filter(|s| s.ppid == self.pid && s.tpgid > 0)
+            .filter_map(|shell| shell.job())
+            .max_by_key(|j| j.starttime)?;
+
+        Process::new(job.pid, strategy).ok()
+    }
+
+    #[cfg(any(target_os = "freebsd", target_os = "openbsd"))]
+    pub fn foreground(&self, _strategy: ProcessName) -> Option<Process> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CMDLINE: &str = "/usr/lib/firefox/firefox-developer-edition\0-P\0work\0";
+
+    #[test]
+    fn cmdline_is_read_like_each_strategy() {
+        let name = |strategy| name_from_cmdline(CMDLINE, strategy);
+        assert_eq!(
+            name(ProcessName::Cmdline),
+            "/usr/lib/firefox/firefox-developer-edition -P work"
+        );
+        assert_eq!(name(ProcessName::Argv0), "firefox-developer-edition");
+        assert_eq!(name(ProcessName::Exe), "firefox-developer-edition");
+        assert_eq!(name(ProcessName::Comm), "firefox-develop");
+        assert_eq!(name_from_cmdline("", ProcessName::Argv0), "");
+    }
+
+    #[test]
+    fn names_convert_unless_they_were_cut_short() {
+        use ProcessName::*;
+        let cmdline = "/usr/lib/firefox/firefox-developer-edition -P work";
+        let program = "firefox-developer-edition";
+
+        assert_eq!(convert_name(cmdline, Cmdline, Cmdline).unwrap(), cmdline);
+        assert_eq!(convert_name(cmdline, Cmdline, Argv0).unwrap(), program);
+        assert_eq!(convert_name(cmdline, Cmdline, Exe).unwrap(), program);
+        assert_eq!(
+            convert_name(cmdline, Cmdline, Comm).unwrap(),
+            "firefox-develop"
+        );
+
+        for from in [Exe, Argv0] {
+            for to in [Exe, Argv0] {
+                assert_eq!(convert_name(program, from, to).unwrap(), program);
+            }
+            assert_eq!(
+                convert_name(program, from, Comm).unwrap(),
+                "firefox-develop"
+            );
+            // The arguments are gone
+            assert_eq!(convert_name(program, from, Cmdline), None);
+        }
+
+        assert_eq!(
+            convert_name("firefox-develop", Comm, Comm).unwrap(),
+            "firefox-develop"
+        );
+        // and so is the end of the name
+        for to in [Exe, Argv0, Cmdline] {
+            assert_eq!(convert_name("firefox-develop", Comm, to), None);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn stat_fields_come_after_the_last_parenthesis() {
+        let stat = "4242 (tmux: client) (1)) S 4000 4242 3900 34817 4242 4194304 \
+                    370 0 0 0 1 0 0 0 20 0 1 0 918273 9916416 1046 18446744073709551615";
+        assert_eq!(
+            Stat::parse(4242, stat),
+            Some(Stat {
+                pid: 4242,
+                ppid: 4000,
+                pgrp: 4242,
+                tpgid: 4242,
+                starttime: 918273,
+            })
+        );
+        assert_eq!(Stat::parse(4242, "4242 (cut off) S 4000"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn own_stat_is_read() {
+        let pid = std::process::id() as pid_t;
+        let stat = Stat::read(pid).unwrap();
+        assert_eq!(stat.pid, pid);
+        assert_eq!(stat.ppid, unsafe { libc::getppid() });
     }
 }
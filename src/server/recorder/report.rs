@@ -0,0 +1,227 @@
+use crate::config::budget_config::Budget;
+use crate::server::recorder::storage::ProcTimes;
+use crate::server::{budget::used, client::DayTotals};
+
+use chrono::{Datelike, Duration, NaiveDate};
+use csv::WriterBuilder;
+use serde::Serialize;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+const REPORT_PREFIX: &str = "week_";
+const REPORT_EXT: &str = ".txt";
+
+// Monday of the week that date falls in
+pub fn week_of(date: NaiveDate) -> NaiveDate {
+    date - Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+pub fn report_path(share: &Path, week: NaiveDate) -> PathBuf {
+    share.join(format!("{}{}{}", REPORT_PREFIX, week, REPORT_EXT))
+}
+
+fn percent(part: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64 * 100.0
+    }
+}
+
+pub fn hms(secs: u64) -> String {
+    format!("{}h {}m {}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[derive(Serialize)]
+struct Entry<'a> {
+    name: &'a str,
+    time: u64,
+    percent: String,
+    score: f64,
+}
+
+// Frozen totals of a week, or of any days from the command line
+pub struct Report {
+    from: NaiveDate,
+    to: NaiveDate,
+    total: u64,
+    productive: u64,
+    weighted: f64,
+    apps: Vec<(String, u64, f64)>,
+    budgets: Vec<String>,
+}
+
+impl Report {
+    pub fn new(week: NaiveDate, proc_times: &ProcTimes) -> Self {
+        Report::range(week, week + Duration::days(6), proc_times)
+    }
+
+    // Each app is listed with its average score
+    pub fn range(from: NaiveDate, to: NaiveDate, proc_times: &ProcTimes) -> Self {
+        let mut apps: Vec<(String, u64, f64)> = proc_times
+            .iter()
+            .map(|(name, t)| (name.to_owned(), t.time, t.score()))
+            .collect();
+
+        // Most used applications first
+        apps.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        let total = proc_times.values().map(|t| t.time).sum();
+        let productive = proc_times.values().map(|t| t.productive).sum();
+        let weighted = proc_times.values().map(|t| t.weighted).sum();
+
+        Report {
+            from,
+            to,
+            total,
+            productive,
+            weighted,
+            apps,
+            budgets: Vec::new(),
+        }
+    }
+
+    // How often each budget was kept on the days recorded
+    pub fn budgets(mut self, budgets: &[Budget], days: &BTreeMap<NaiveDate, DayTotals>) -> Self {
+        self.budgets = budgets
+            .iter()
+            .map(|b| {
+                let times: Vec<f64> = days.values().map(|d| used(b, d) as f64 / 60.0).collect();
+                let mut kept = Vec::new();
+                if let Some(max) = b.max() {
+                    let under = times.iter().filter(|t| **t <= max).count();
+                    kept.push(format!(
+                        "at most {}m on {} of {} days",
+                        max,
+                        under,
+                        days.len()
+                    ));
+                }
+                if let Some(min) = b.min() {
+                    let met = times.iter().filter(|t| **t >= min).count();
+                    kept.push(format!(
+                        "at least {}m on {} of {} days",
+                        min,
+                        met,
+                        days.len()
+                    ));
+                }
+                format!("{}: {}", b.name(), kept.join(", "))
+            })
+            .collect();
+        self
+    }
+
+    // Time weighted average of the scores, from -100% to 100%
+    pub fn score_percent(&self) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.weighted / self.total as f64 * 100.0
+        }
+    }
+
+    pub fn productive_percent(&self) -> f64 {
+        percent(self.productive, self.total)
+    }
+
+    pub fn write(&self, share: &Path) -> csv::Result<PathBuf> {
+        let path = report_path(share, self.from);
+        self.write_to(BufWriter::new(File::create(&path)?))?;
+        Ok(path)
+    }
+
+    pub fn write_to(&self, mut f: impl Write) -> csv::Result<()> {
+        if self.from == self.to {
+            writeln!(f, "Day of {}", self.from)?;
+        } else if self.from == week_of(self.from) && self.to == self.from + Duration::days(6) {
+            writeln!(f, "Week of {} to {}", self.from, self.to)?;
+        } else {
+            writeln!(f, "From {} to {}", self.from, self.to)?;
+        }
+        writeln!(f, "Total time: {}", hms(self.total))?;
+        writeln!(
+            f,
+            "Productive: {} ({:.2}%)",
+            hms(self.productive),
+            self.productive_percent()
+        )?;
+        writeln!(f, "Score: {:.2}%", self.score_percent())?;
+        if !self.budgets.is_empty() {
+            writeln!(f, "Budgets:")?;
+            for b in &self.budgets {
+                writeln!(f, "  {}", b)?;
+            }
+        }
+        writeln!(f)?;
+
+        let mut writer = WriterBuilder::new().from_writer(f);
+        for (name, time, score) in &self.apps {
+            writer.serialize(Entry {
+                name,
+                time: *time,
+                percent: format!("{:.2}", percent(*time, self.total)),
+                score: *score,
+            })?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::recorder::storage::Totals;
+
+    fn week() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 10, 12).unwrap()
+    }
+
+    #[test]
+    fn time_is_counted_with_the_score_it_had() {
+        // Productive in the morning and not in the evening
+        let mut slack = Totals::new(3600, 1.0);
+        slack.add(3600, -1.0);
+        let mut proc_times = ProcTimes::new();
+        proc_times.insert("slack".to_owned(), slack);
+        proc_times.insert("code".to_owned(), Totals::new(1800, 0.5));
+
+        let report = Report::new(week(), &proc_times);
+        assert_eq!(report.total, 9000);
+        assert_eq!(report.productive, 5400);
+        assert_eq!(report.productive_percent(), 60.0);
+        assert_eq!(report.score_percent(), 10.0);
+        assert_eq!(report.apps[0], ("slack".to_owned(), 7200, 0.0));
+        assert_eq!(report.apps[1], ("code".to_owned(), 1800, 0.5));
+    }
+
+    #[test]
+    fn budgets_say_on_how_many_days_they_were_kept() {
+        let budgets: Vec<Budget> = vec![
+            toml::from_str("app = \"firefox\"\nmax = 30.0").unwrap(),
+            toml::from_str("category = \"productive\"\nmin = 60.0\nmax = 240.0").unwrap(),
+        ];
+        let mut days = BTreeMap::new();
+        for (day, browsing, work) in [(12, 20, 90), (13, 45, 30), (14, 10, 300)] {
+            let mut totals = DayTotals::default();
+            totals.add("firefox:docs.rs", "productive", browsing * 60);
+            totals.add("alacritty:nvim", "productive", work * 60);
+            days.insert(NaiveDate::from_ymd_opt(2026, 10, day).unwrap(), totals);
+        }
+
+        let report = Report::new(week(), &ProcTimes::new()).budgets(&budgets, &days);
+        assert_eq!(
+            report.budgets,
+            vec![
+                "firefox: at most 30m on 2 of 3 days",
+                "productive: at most 240m on 2 of 3 days, at least 60m on 3 of 3 days",
+            ]
+        );
+    }
+}
@@ -0,0 +1,234 @@
+use crate::config::profile_config::DEFAULT_PROFILE;
+use crate::config::recorder_config::{PRODUCTIVE, UNCATEGORIZED};
+
+use chrono::{DateTime, Local, NaiveDate};
+use csv::{ReaderBuilder, WriterBuilder};
+use serde::{Deserialize, Serialize};
+
+use std::fs::{remove_file, rename, File, OpenOptions};
+use std::io::{prelude::*, BufReader};
+use std::path::Path;
+
+pub const SPAN_FILE: &str = "spans.csv";
+// The span still open at the last write, replaced on every write
+const OPEN_SPAN_FILE: &str = "open_span.csv";
+const OPEN_SPAN_TEMP: &str = "open_span.csv.tmp";
+const SPAN_TEMP: &str = "spans.csv.tmp";
+const SPAN_HEADER: &str = "start,end,pid,name,class,title,category,score,profile";
+
+// A single interval of focus on one process
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Span {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>,
+    pub pid: i32,
+    pub name: String,
+    pub class: String,
+    pub title: String,
+    pub category: String,
+    pub score: f64,
+    // Active while the span was recorded
+    pub profile: String,
+}
+
+impl Span {
+    pub fn secs(&self) -> u64 {
+        (self.end - self.start).num_seconds().max(0) as u64
+    }
+}
+
+fn default_profile() -> String {
+    DEFAULT_PROFILE.to_owned()
+}
+
+// A span written with any of the columns the log has had,
+// the ones added since are filled in
+#[derive(Deserialize)]
+struct Row {
+    start: DateTime<Local>,
+    end: DateTime<Local>,
+    pid: i32,
+    name: String,
+    #[serde(default)]
+    class: String,
+    #[serde(default)]
+    title: String,
+    category: Option<String>,
+    score: Option<f64>,
+    // Before categories a span was only productive or not
+    is_prod: Option<bool>,
+    #[serde(default = "default_profile")]
+    profile: String,
+}
+
+impl From<Row> for Span {
+    fn from(row: Row) -> Span {
+        // With the default weights of the time
+        let (category, score) = match row.is_prod {
+            Some(true) => (PRODUCTIVE, 1.0),
+            _ => (UNCATEGORIZED, -1.0),
+        };
+        Span {
+            start: row.start,
+            end: row.end,
+            pid: row.pid,
+            name: row.name,
+            class: row.class,
+            title: row.title,
+            category: row.category.unwrap_or_else(|| category.to_owned()),
+            score: row.score.unwrap_or(score),
+            profile: row.profile,
+        }
+    }
+}
+
+fn read_rows(path: &Path) -> csv::Result<Vec<Span>> {
+    ReaderBuilder::new()
+        .from_path(path)?
+        .into_deserialize::<Row>()
+        .map(|r| r.map(Span::from))
+        .collect()
+}
+
+// A log written with other columns can't be appended to, so its
+// spans are rewritten with the current ones before it's used
+pub fn upgrade_spans(share: &Path) -> csv::Result<()> {
+    let path = share.join(SPAN_FILE);
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let mut header = String::new();
+    BufReader::new(File::open(&path)?).read_line(&mut header)?;
+    if header.is_empty() || header.trim_end() == SPAN_HEADER {
+        return Ok(());
+    }
+
+    rewrite_spans(share, &read_rows(&path)?)?;
+    println!("Upgraded the span log to the new columns");
+    Ok(())
+}
+
+// Renames the spans in the log, it keeps as many rows
+// so the totals still count the same ones
+pub fn rename_spans(share: &Path, rename: &dyn Fn(&str) -> Option<String>) -> csv::Result<()> {
+    let path = share.join(SPAN_FILE);
+    if !path.exists() {
+        return Ok(());
+    }
+
+    let mut spans = read_rows(&path)?;
+    let mut renamed = false;
+    for span in spans.iter_mut() {
+        if let Some(name) = rename(&span.name) {
+            span.name = name;
+            renamed = true;
+        }
+    }
+    if renamed {
+        rewrite_spans(share, &spans)?;
+    }
+    Ok(())
+}
+
+// Replaces the whole log at once, a crash leaves either the old one or the new one
+fn rewrite_spans(share: &Path, spans: &[Span]) -> csv::Result<()> {
+    let temp = share.join(SPAN_TEMP);
+    let mut writer = WriterBuilder::new().from_path(&temp)?;
+    for span in spans {
+        writer.serialize(span)?;
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    rename(&temp, share.join(SPAN_FILE))?;
+    Ok(())
+}
+
+// Spans in the log that started on or after the day
+pub fn read_spans(share: &Path, day: NaiveDate) -> csv::Result<Vec<Span>> {
+    let path = share.join(SPAN_FILE);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut spans = read_rows(&path)?;
+    spans.retain(|s| s.start.date_naive() >= day);
+    Ok(spans)
+}
+
+// Spans are only ever appended, the header is written when the log is new.
+// They're on disk before the totals that count them are written.
+pub fn append_spans(share: &Path, spans: &[Span]) -> csv::Result<()> {
+    if spans.is_empty() {
+        return Ok(());
+    }
+    let f = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(share.join(SPAN_FILE))?;
+    let is_new = f.metadata()?.len() == 0;
+
+    let mut writer = WriterBuilder::new().has_headers(is_new).from_writer(f);
+    for span in spans {
+        writer.serialize(span)?;
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    if is_new {
+        File::open(share)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// A crash while spans were appended can leave the last one cut off,
+// it never made it into the totals so it's dropped
+pub fn cut_torn_span(share: &Path) -> std::io::Result<()> {
+    let path = share.join(SPAN_FILE);
+    if !path.exists() {
+        return Ok(());
+    }
+    let contents = std::fs::read(&path)?;
+    if contents.is_empty() || contents.ends_with(b"\n") {
+        return Ok(());
+    }
+    let whole = contents
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map_or(0, |i| i + 1);
+    let f = OpenOptions::new().write(true).open(&path)?;
+    f.set_len(whole as u64)?;
+    f.sync_all()?;
+    println!("Dropped a span cut off at the end of {}", path.display());
+    Ok(())
+}
+
+// Replaces the open span, which is on disk before it takes the old one's place
+pub fn write_open_span(share: &Path, span: Option<&Span>) -> csv::Result<()> {
+    let path = share.join(OPEN_SPAN_FILE);
+    let span = match span {
+        Some(s) => s,
+        None => {
+            if path.exists() {
+                remove_file(&path)?;
+            }
+            return Ok(());
+        }
+    };
+
+    let temp = share.join(OPEN_SPAN_TEMP);
+    let mut writer = WriterBuilder::new().from_path(&temp)?;
+    writer.serialize(span)?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    rename(&temp, &path)?;
+    Ok(())
+}
+
+// The span that was open when the last write before a crash happened
+pub fn read_open_span(share: &Path) -> csv::Result<Option<Span>> {
+    let path = share.join(OPEN_SPAN_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(read_rows(&path)?.into_iter().next())
+}
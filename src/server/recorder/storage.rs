@@ -0,0 +1,108 @@
+mod csv_store;
+mod sqlite_store;
+pub use csv_store::CsvStorage;
+pub use sqlite_store::SqliteStorage;
+
+use crate::config::recorder_config::{ProcessName, StorageKind};
+use crate::server::recorder::{span::Span, RecorderResult};
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::NaiveDate;
+
+// A name's seconds, added up span by span as its score can change
+// with the rules' hours and the weekday
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Totals {
+    pub time: u64,
+    // Seconds with a score above 0
+    pub productive: u64,
+    // Seconds times their score
+    pub weighted: f64,
+}
+
+impl Totals {
+    // Totals of time that all had the same score
+    pub fn new(secs: u64, score: f64) -> Self {
+        let mut totals = Totals::default();
+        totals.add(secs, score);
+        totals
+    }
+
+    pub fn add(&mut self, secs: u64, score: f64) {
+        self.time += secs;
+        if score > 0.0 {
+            self.productive += secs;
+        }
+        self.weighted += secs as f64 * score;
+    }
+
+    pub fn merge(&mut self, other: &Totals) {
+        self.time += other.time;
+        self.productive += other.productive;
+        self.weighted += other.weighted;
+    }
+
+    // Time weighted average of the scores
+    pub fn score(&self) -> f64 {
+        if self.time == 0 {
+            0.0
+        } else {
+            self.weighted / self.time as f64
+        }
+    }
+}
+
+// The week's totals of each name
+pub type ProcTimes = HashMap<String, Totals>;
+
+// Where the recorder keeps its spans and weekly totals
+pub trait Storage: Send + Sync {
+    // The week the stored totals belong to and the totals themselves
+    fn load(&self) -> RecorderResult<(NaiveDate, ProcTimes)>;
+
+    // Appends the new spans and stores the current week's totals.
+    // The open span replaces the one from the last write, it's only
+    // added to the spans and totals if the daemon didn't get to close it.
+    fn write(
+        &self,
+        spans: &[Span],
+        proc_times: &ProcTimes,
+        open: Option<&Span>,
+    ) -> RecorderResult<()>;
+
+    // Spans that started on or after the day
+    fn spans_since(&self, day: NaiveDate) -> RecorderResult<Vec<Span>>;
+
+    // Renames every stored name the function returns a new name for,
+    // merging the totals of names that end up the same.
+    // Returns how many names were changed.
+    fn rename(&self, rename: &dyn Fn(&str) -> Option<String>) -> RecorderResult<usize>;
+
+    // Every stored name
+    fn names(&self) -> RecorderResult<Vec<String>>;
+
+    // The strategy the stored process names were read with,
+    // None for data from before it was kept
+    fn process_name(&self) -> RecorderResult<Option<ProcessName>>;
+
+    fn set_process_name(&self, strategy: ProcessName) -> RecorderResult<()>;
+}
+
+pub fn open(kind: StorageKind, share: &Path) -> RecorderResult<Arc<dyn Storage>> {
+    Ok(match kind {
+        StorageKind::Csv => Arc::new(CsvStorage::new(share)?),
+        StorageKind::Sqlite => Arc::new(SqliteStorage::new(share)?),
+    })
+}
+
+// Only for reading spans while the daemon may be writing,
+// nothing is recovered, upgraded or created
+pub fn open_read_only(kind: StorageKind, share: &Path) -> RecorderResult<Arc<dyn Storage>> {
+    Ok(match kind {
+        StorageKind::Csv => Arc::new(CsvStorage::read_only(share)?),
+        StorageKind::Sqlite => Arc::new(SqliteStorage::read_only(share)?),
+    })
+}
@@ -0,0 +1,646 @@
+use crate::config::recorder_config::ProcessName;
+use crate::server::recorder::{
+    report::week_of,
+    span::{
+        append_spans, cut_torn_span, read_open_span, read_spans, rename_spans, upgrade_spans,
+        write_open_span, Span,
+    },
+    storage::{ProcTimes, Storage, Totals},
+    RecorderError, RecorderResult,
+};
+
+use std::collections::HashMap;
+use std::fs::{copy, read_to_string, rename, write, File, OpenOptions};
+use std::io::{prelude::*, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local, NaiveDate};
+use csv::{ReaderBuilder, WriterBuilder};
+use serde::Deserialize;
+
+const DATA_FILE: &str = "data.csv";
+const DATA_BACKUP: &str = "data.csv.bak";
+const DATA_TEMP: &str = "data.csv.tmp";
+const DATA_HEADER: [&str; 4] = ["name", "time", "productive", "weighted"];
+// The first line of the data file, how many rows of the span log
+// its totals include
+const LOGGED_PREFIX: &str = "# logged spans: ";
+// Holds the strategy the names in data.csv were read with
+const PROCESS_NAME_FILE: &str = "process_name";
+
+// A row of the data file in any of the columns it has had
+#[derive(Debug, Deserialize)]
+struct Data {
+    name: String,
+    time: u64,
+    productive: Option<u64>,
+    weighted: Option<f64>,
+    // Older files have one score for all of the time,
+    // and is_prod before there were scores
+    score: Option<f64>,
+    is_prod: Option<bool>,
+}
+
+impl Data {
+    fn totals(&self) -> Totals {
+        match (self.productive, self.weighted) {
+            (Some(productive), Some(weighted)) => Totals {
+                time: self.time,
+                productive,
+                weighted,
+            },
+            _ => {
+                let is_prod = self.is_prod.map(|p| if p { 1.0 } else { -1.0 });
+                Totals::new(self.time, self.score.or(is_prod).unwrap_or(0.0))
+            }
+        }
+    }
+}
+
+// Spans are appended to spans.csv and the week's totals
+// replace data.csv on every write, the previous totals are kept
+// in data.csv.bak in case data.csv is found broken.
+// The totals say how many spans they count, so spans logged
+// before a crash kept the totals from being written aren't lost.
+pub struct CsvStorage {
+    share: PathBuf,
+}
+
+impl CsvStorage {
+    pub fn new(share: &Path) -> RecorderResult<CsvStorage> {
+        if !share.is_dir() {
+            return Err(RecorderError::PathDoesNotExistError(
+                share.to_string_lossy().into_owned(),
+            ));
+        }
+
+        let storage = CsvStorage {
+            share: share.to_owned(),
+        };
+        cut_torn_span(share)?;
+        let exists = |p: PathBuf| p.exists();
+        if !exists(storage.data()) && !exists(storage.backup()) && !exists(storage.temp()) {
+            storage.write_totals(&ProcTimes::new(), storage.span_count()?)?;
+        }
+        storage.recover()?;
+        upgrade_spans(share)?;
+        storage.catch_up()?;
+        Ok(storage)
+    }
+
+    // Reads the files as they are
+    pub fn read_only(share: &Path) -> RecorderResult<CsvStorage> {
+        if !share.is_dir() {
+            return Err(RecorderError::PathDoesNotExistError(
+                share.to_string_lossy().into_owned(),
+            ));
+        }
+        Ok(CsvStorage {
+            share: share.to_owned(),
+        })
+    }
+
+    // Adds the spans logged after the totals were last written to them.
+    // A span left open by a crash ends where it was last written,
+    // unless it was logged closed before the crash.
+    fn catch_up(&self) -> RecorderResult<()> {
+        let logged = read_spans(&self.share, NaiveDate::MIN)?;
+        let counted = CsvStorage::parse_logged(&self.data())?;
+        // Older data files counted every span that was logged
+        let mut missing = logged[counted.unwrap_or(logged.len()).min(logged.len())..].to_vec();
+
+        let open = read_open_span(&self.share)?.filter(|o| {
+            !logged
+                .iter()
+                .any(|s| s.start == o.start && s.name == o.name)
+        });
+        if let Some(span) = &open {
+            append_spans(&self.share, std::slice::from_ref(span))?;
+            println!(
+                "Recovered {} seconds of {} that were open when the daemon stopped",
+                span.secs(),
+                span.name
+            );
+            missing.push(span.to_owned());
+        } else if !missing.is_empty() {
+            println!(
+                "Added {} spans logged after the totals were last written",
+                missing.len()
+            );
+        }
+
+        let count = logged.len() + open.iter().count();
+        if !missing.is_empty() || counted != Some(count) {
+            // The modified time tells which week the data is from
+            let mut modified = self.data().metadata()?.modified()?;
+            let mut map = self.parse_data()?;
+            for span in &missing {
+                map.entry(span.name.to_owned())
+                    .or_default()
+                    .add(span.secs(), span.score);
+                modified = modified.max(span.end.into());
+            }
+            self.write_totals(&map, count)?;
+            self.set_data_modified(modified)?;
+        }
+        write_open_span(&self.share, None)?;
+        Ok(())
+    }
+
+    fn span_count(&self) -> RecorderResult<usize> {
+        Ok(read_spans(&self.share, NaiveDate::MIN)?.len())
+    }
+
+    // How many spans the totals count, None for files from before it was kept
+    fn parse_logged(path: &Path) -> RecorderResult<Option<usize>> {
+        let mut line = String::new();
+        BufReader::new(File::open(path)?).read_line(&mut line)?;
+        Ok(line
+            .strip_prefix(LOGGED_PREFIX)
+            .and_then(|n| n.trim_end().parse().ok()))
+    }
+
+    // How many spans the data file counts
+    fn logged(&self) -> RecorderResult<usize> {
+        match CsvStorage::parse_logged(&self.data())? {
+            Some(n) => Ok(n),
+            None => self.span_count(),
+        }
+    }
+
+    fn data(&self) -> PathBuf {
+        self.share.join(DATA_FILE)
+    }
+
+    fn backup(&self) -> PathBuf {
+        self.share.join(DATA_BACKUP)
+    }
+
+    fn temp(&self) -> PathBuf {
+        self.share.join(DATA_TEMP)
+    }
+
+    fn parse_file(path: &Path) -> RecorderResult<ProcTimes> {
+        // Older versions left the file empty when there was nothing to write
+        if path.metadata()?.len() == 0 {
+            return Ok(ProcTimes::new());
+        }
+
+        let mut reader = ReaderBuilder::new().comment(Some(b'#')).from_path(path)?;
+        // A file cut off before its header ended has no columns to read
+        if !reader.headers()?.iter().any(|h| h == "time") {
+            return Err(RecorderError::CorruptDataError(
+                path.to_string_lossy().into_owned(),
+                "the header is missing or incomplete".to_owned(),
+            ));
+        }
+
+        let mut map = HashMap::new();
+        for r in reader.into_deserialize() {
+            let data: Data = r?;
+            map.insert(data.name.to_owned(), data.totals());
+        }
+        Ok(map)
+    }
+
+    fn parse_data(&self) -> RecorderResult<ProcTimes> {
+        CsvStorage::parse_file(&self.data())
+    }
+
+    // Every record ends with a newline, a temp file cut off
+    // while it was written is missing the last one
+    fn parse_temp(&self) -> RecorderResult<ProcTimes> {
+        let contents = std::fs::read(self.temp())?;
+        if contents.last() != Some(&b'\n') {
+            return Err(RecorderError::CorruptDataError(
+                self.temp().to_string_lossy().into_owned(),
+                "it was cut off".to_owned(),
+            ));
+        }
+        CsvStorage::parse_file(&self.temp())
+    }
+
+    // The data file is only ever replaced whole, but it can still be lost
+    // or damaged outside of us, or by versions that moved it to the backup
+    // before the temp file took its place.
+    // A whole temp file is the newest totals and replaces it when it can't
+    // be read, then the backup, otherwise the week starts over.
+    // A broken data file is moved aside.
+    fn recover(&self) -> RecorderResult<()> {
+        let problem = if self.data().exists() {
+            match self.parse_data() {
+                Ok(_) => return Ok(()),
+                Err(e) => e.to_string(),
+            }
+        } else {
+            "it is missing".to_owned()
+        };
+        println!("{} can't be read: {}", self.data().display(), problem);
+
+        if self.data().exists() {
+            let aside = self.share.join(format!(
+                "{}.{}",
+                DATA_FILE,
+                Local::now().format("%Y%m%d%H%M%S")
+            ));
+            rename(self.data(), &aside)?;
+            println!("Moved it to {}", aside.display());
+        }
+
+        if self.temp().exists() {
+            match self.parse_temp() {
+                Ok(map) => {
+                    rename(self.temp(), self.data())?;
+                    File::open(&self.share)?.sync_all()?;
+                    println!(
+                        "Recovered the totals of {} names from {}",
+                        map.len(),
+                        self.temp().display()
+                    );
+                    return Ok(());
+                }
+                Err(e) => println!("{} can't be used: {}", self.temp().display(), e),
+            }
+        }
+
+        match CsvStorage::parse_file(&self.backup()) {
+            Ok(map) => {
+                // The backup's modified time tells which week it is from
+                let modified = self.backup().metadata()?.modified()?;
+                copy(self.backup(), self.data())?;
+                self.set_data_modified(modified)?;
+                println!(
+                    "Recovered the totals of {} names from {} last written {}",
+                    map.len(),
+                    self.backup().display(),
+                    DateTime::<Local>::from(modified).format("%Y-%m-%d %H:%M:%S")
+                );
+            }
+            Err(e) => {
+                println!(
+                    "The backup {} can't be read either: {}\nStarting with empty totals",
+                    self.backup().display(),
+                    e
+                );
+                self.write_totals(&ProcTimes::new(), self.span_count()?)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The totals are written to a temp file that replaces the data file
+    // once it is on disk, so the data file is always whole.
+    // The backup is a copy, the data file is never missing.
+    fn write_totals(&self, proc_times: &ProcTimes, logged: usize) -> RecorderResult<()> {
+        let first = format!("{}{}\n", LOGGED_PREFIX, logged);
+        let mut writer = WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(first.into_bytes());
+        writer.write_record(DATA_HEADER)?;
+        for (name, t) in proc_times.iter() {
+            writer.serialize((name, t.time, t.productive, t.weighted))?;
+        }
+        let contents = writer
+            .into_inner()
+            .map_err(|e| RecorderError::FileError(e.into_error()))?;
+
+        let mut f = File::create(self.temp())?;
+        f.write_all(&contents)?;
+        f.sync_all()?;
+
+        if self.data().exists() {
+            copy(self.data(), self.backup())?;
+        }
+        rename(self.temp(), self.data())?;
+        File::open(&self.share)?.sync_all()?;
+        Ok(())
+    }
+
+    fn set_data_modified(&self, modified: SystemTime) -> RecorderResult<()> {
+        OpenOptions::new()
+            .write(true)
+            .open(self.data())?
+            .set_modified(modified)?;
+        Ok(())
+    }
+
+    // The week the data file belongs to is the week it was last written in
+    fn data_week(&self) -> RecorderResult<NaiveDate> {
+        let modified = self.data().metadata()?.modified()?;
+        Ok(week_of(
+            DateTime::<Local>::from(modified).naive_local().date(),
+        ))
+    }
+}
+
+impl Storage for CsvStorage {
+    fn load(&self) -> RecorderResult<(NaiveDate, ProcTimes)> {
+        Ok((self.data_week()?, self.parse_data()?))
+    }
+
+    fn write(
+        &self,
+        spans: &[Span],
+        proc_times: &ProcTimes,
+        open: Option<&Span>,
+    ) -> RecorderResult<()> {
+        let logged = self.logged()? + spans.len();
+        append_spans(&self.share, spans)?;
+        self.write_totals(proc_times, logged)?;
+        write_open_span(&self.share, open)?;
+        Ok(())
+    }
+
+    fn spans_since(&self, day: NaiveDate) -> RecorderResult<Vec<Span>> {
+        Ok(read_spans(&self.share, day)?)
+    }
+
+    // The span log is append only and keeps its original names
+    fn rename(&self, rename: &dyn Fn(&str) -> Option<String>) -> RecorderResult<usize> {
+        let mut renamed = 0;
+        let mut map = ProcTimes::new();
+        for (name, totals) in self.parse_data()? {
+            let name = match rename(&name) {
+                Some(n) => {
+                    renamed += 1;
+                    n
+                }
+                None => name,
+            };
+            map.entry(name).or_default().merge(&totals);
+        }
+
+        if renamed > 0 {
+            rename_spans(&self.share, rename)?;
+            // Keep the modified time, it tells which week the data is from
+            let modified = self.data().metadata()?.modified()?;
+            self.write_totals(&map, self.logged()?)?;
+            self.set_data_modified(modified)?;
+        }
+        Ok(renamed)
+    }
+
+    fn names(&self) -> RecorderResult<Vec<String>> {
+        Ok(self.parse_data()?.into_keys().collect())
+    }
+
+    fn process_name(&self) -> RecorderResult<Option<ProcessName>> {
+        let path = self.share.join(PROCESS_NAME_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(ProcessName::from_name(read_to_string(path)?.trim()))
+    }
+
+    fn set_process_name(&self, strategy: ProcessName) -> RecorderResult<()> {
+        write(
+            self.share.join(PROCESS_NAME_FILE),
+            format!("{}\n", strategy.name()),
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::recorder_config::PRODUCTIVE;
+    use crate::server::recorder::span::SPAN_FILE;
+
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    fn share(name: &str) -> PathBuf {
+        let share = std::env::temp_dir().join(format!("septem-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&share);
+        create_dir_all(&share).unwrap();
+        share
+    }
+
+    fn totals(storage: &CsvStorage) -> Vec<(String, u64)> {
+        let mut totals: Vec<_> = storage
+            .parse_data()
+            .unwrap()
+            .into_iter()
+            .map(|(n, t)| (n, t.time))
+            .collect();
+        totals.sort();
+        totals
+    }
+
+    fn span(name: &str, secs: i64) -> Span {
+        let start = Local::now() - chrono::Duration::seconds(secs);
+        Span {
+            start,
+            end: start + chrono::Duration::seconds(secs),
+            pid: 1,
+            name: name.to_owned(),
+            class: String::new(),
+            title: String::new(),
+            category: String::new(),
+            score: 1.0,
+            profile: "default".to_owned(),
+        }
+    }
+
+    #[test]
+    fn open_span_is_recovered_once() {
+        let share = share("open");
+        let storage = CsvStorage::new(&share).unwrap();
+        let open = span("vim", 60);
+        storage.write(&[], &ProcTimes::new(), Some(&open)).unwrap();
+
+        // Killed before it closed
+        let storage = CsvStorage::new(&share).unwrap();
+        assert_eq!(totals(&storage), vec![("vim".to_owned(), 60)]);
+        assert_eq!(
+            storage.spans_since(open.start.date_naive()).unwrap().len(),
+            1
+        );
+
+        let storage = CsvStorage::new(&share).unwrap();
+        assert_eq!(totals(&storage), vec![("vim".to_owned(), 60)]);
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn span_logged_before_the_totals_is_counted_once() {
+        let share = share("logged");
+        let storage = CsvStorage::new(&share).unwrap();
+        let open = span("vim", 60);
+        storage.write(&[], &ProcTimes::new(), Some(&open)).unwrap();
+        // Killed after the span was logged but before the totals were written
+        append_spans(&share, std::slice::from_ref(&open)).unwrap();
+
+        let storage = CsvStorage::new(&share).unwrap();
+        assert_eq!(totals(&storage), vec![("vim".to_owned(), 60)]);
+        assert_eq!(
+            storage.spans_since(open.start.date_naive()).unwrap().len(),
+            1
+        );
+
+        let storage = CsvStorage::new(&share).unwrap();
+        assert_eq!(totals(&storage), vec![("vim".to_owned(), 60)]);
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn open_span_in_the_totals_is_not_counted_again() {
+        let share = share("counted");
+        let storage = CsvStorage::new(&share).unwrap();
+        let open = span("vim", 60);
+        let mut map = ProcTimes::new();
+        map.insert("vim".to_owned(), Totals::new(60, 1.0));
+        // Killed after the totals were written but before the open one was replaced
+        storage
+            .write(std::slice::from_ref(&open), &map, None)
+            .unwrap();
+        write_open_span(&share, Some(&open)).unwrap();
+
+        let storage = CsvStorage::new(&share).unwrap();
+        assert_eq!(totals(&storage), vec![("vim".to_owned(), 60)]);
+        assert_eq!(
+            storage.spans_since(open.start.date_naive()).unwrap().len(),
+            1
+        );
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn torn_span_is_dropped() {
+        let share = share("torn");
+        let storage = CsvStorage::new(&share).unwrap();
+        let mut map = ProcTimes::new();
+        map.insert("vim".to_owned(), Totals::new(60, 1.0));
+        storage.write(&[span("vim", 60)], &map, None).unwrap();
+        let mut log = std::fs::OpenOptions::new()
+            .append(true)
+            .open(share.join(SPAN_FILE))
+            .unwrap();
+        log.write_all(b"2026-10-19T09:00:00+00:00,2026-10-19T09")
+            .unwrap();
+
+        let storage = CsvStorage::new(&share).unwrap();
+        assert_eq!(totals(&storage), vec![("vim".to_owned(), 60)]);
+        assert_eq!(storage.span_count().unwrap(), 1);
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn backup_is_a_copy() {
+        let share = share("backup");
+        let storage = CsvStorage::new(&share).unwrap();
+        let mut map = ProcTimes::new();
+        map.insert("vim".to_owned(), Totals::new(10, 1.0));
+        storage.write_totals(&map, 0).unwrap();
+        map.insert("vim".to_owned(), Totals::new(20, 1.0));
+        storage.write_totals(&map, 0).unwrap();
+
+        assert_eq!(totals(&storage), vec![("vim".to_owned(), 20)]);
+        let backup = CsvStorage::parse_file(&storage.backup()).unwrap();
+        assert_eq!(backup["vim"].time, 10);
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn recovers_a_whole_temp_file_before_the_backup() {
+        let share = share("temp");
+        write(share.join(DATA_BACKUP), "name,time,score\nvim,10,1.0\n").unwrap();
+        write(share.join(DATA_TEMP), "name,time,score\nvim,20,1.0\n").unwrap();
+
+        let storage = CsvStorage::new(&share).unwrap();
+        assert_eq!(totals(&storage), vec![("vim".to_owned(), 20)]);
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn recovers_the_backup_when_the_temp_file_was_cut_off() {
+        let share = share("cut");
+        write(share.join(DATA_BACKUP), "name,time,score\nvim,10,1.0\n").unwrap();
+        write(
+            share.join(DATA_TEMP),
+            "name,time,score\nvim,20,1.0\nemacs,3",
+        )
+        .unwrap();
+
+        let storage = CsvStorage::new(&share).unwrap();
+        assert_eq!(totals(&storage), vec![("vim".to_owned(), 10)]);
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn span_log_with_old_columns_is_kept() {
+        let share = share("columns");
+        let start = Local::now() - chrono::Duration::seconds(60);
+        let end = start + chrono::Duration::seconds(60);
+        let log = format!(
+            "start,end,pid,name,title,is_prod\n{},{},1,vim,notes,true\n",
+            start.to_rfc3339(),
+            end.to_rfc3339()
+        );
+        std::fs::write(share.join(SPAN_FILE), log).unwrap();
+
+        let storage = CsvStorage::new(&share).unwrap();
+        storage
+            .write(&[span("mpv", 30)], &ProcTimes::new(), None)
+            .unwrap();
+        let spans = storage.spans_since(start.date_naive()).unwrap();
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].name, "vim");
+        assert_eq!(spans[0].title, "notes");
+        assert_eq!(spans[0].category, PRODUCTIVE);
+        assert_eq!(spans[0].profile, "default");
+        assert_eq!(spans[1].name, "mpv");
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn totals_keep_productive_and_weighted_time() {
+        let share = share("weighted");
+        let storage = CsvStorage::new(&share).unwrap();
+        let mut vim = Totals::new(60, 1.0);
+        vim.add(30, -0.5);
+        let mut map = ProcTimes::new();
+        map.insert("vim".to_owned(), vim);
+        storage.write_totals(&map, 0).unwrap();
+
+        assert_eq!(storage.parse_data().unwrap()["vim"], vim);
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn older_data_files_use_their_score_for_all_the_time() {
+        let share = share("scores");
+        write(share.join(DATA_FILE), "name,time,score\nvim,60,0.5\n").unwrap();
+        let storage = CsvStorage::new(&share).unwrap();
+        assert_eq!(storage.parse_data().unwrap()["vim"], Totals::new(60, 0.5));
+
+        write(share.join(DATA_FILE), "name,time,is_prod\nvim,60,false\n").unwrap();
+        assert_eq!(storage.parse_data().unwrap()["vim"], Totals::new(60, -1.0));
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn rename_renames_the_totals_and_the_spans() {
+        let share = share("rename");
+        let storage = CsvStorage::new(&share).unwrap();
+        let (vim, mpv) = (span("vim", 60), span("mpv", 30));
+        let mut map = ProcTimes::new();
+        map.insert("vim".to_owned(), Totals::new(60, 1.0));
+        map.insert("mpv".to_owned(), Totals::new(30, 1.0));
+        storage.write(&[vim.clone(), mpv], &map, None).unwrap();
+
+        let renamed = storage
+            .rename(&|n| (n == "vim").then(|| "nvim".to_owned()))
+            .unwrap();
+        assert_eq!(renamed, 1);
+        let mut names = storage.names().unwrap();
+        names.sort();
+        assert_eq!(names, vec!["mpv", "nvim"]);
+        let spans = storage.spans_since(vim.start.date_naive()).unwrap();
+        let mut names: Vec<_> = spans.iter().map(|s| s.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["mpv", "nvim"]);
+        remove_dir_all(share).unwrap();
+    }
+}
@@ -0,0 +1,550 @@
+use crate::config::recorder_config::ProcessName;
+use crate::server::recorder::{
+    report::week_of,
+    span::Span,
+    storage::{ProcTimes, Storage, Totals},
+    RecorderError, RecorderResult,
+};
+
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::{Local, NaiveDate};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+
+const DATABASE_FILE: &str = "septem.db";
+const SCHEMA_VERSION: u32 = 8;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS schema_version (
+        version INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS spans (
+        id      INTEGER PRIMARY KEY,
+        start   TEXT    NOT NULL,
+        end     TEXT    NOT NULL,
+        pid     INTEGER NOT NULL,
+        name    TEXT    NOT NULL,
+        class   TEXT    NOT NULL DEFAULT '',
+        title    TEXT    NOT NULL DEFAULT '',
+        category TEXT    NOT NULL DEFAULT '',
+        score    REAL    NOT NULL DEFAULT 0,
+        is_prod  INTEGER NOT NULL,
+        profile  TEXT    NOT NULL DEFAULT 'default'
+    );
+    CREATE TABLE IF NOT EXISTS daily (
+        day     TEXT    NOT NULL,
+        name    TEXT    NOT NULL,
+        time    INTEGER NOT NULL,
+        score   REAL    NOT NULL DEFAULT 0,
+        is_prod INTEGER NOT NULL,
+        productive INTEGER NOT NULL DEFAULT 0,
+        weighted   REAL    NOT NULL DEFAULT 0,
+        PRIMARY KEY (day, name)
+    );
+    CREATE TABLE IF NOT EXISTS meta (
+        key     TEXT    PRIMARY KEY,
+        value   TEXT    NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS open_span (
+        start    TEXT    NOT NULL,
+        end      TEXT    NOT NULL,
+        pid      INTEGER NOT NULL,
+        name     TEXT    NOT NULL,
+        class    TEXT    NOT NULL,
+        title    TEXT    NOT NULL,
+        category TEXT    NOT NULL,
+        score    REAL    NOT NULL,
+        profile  TEXT    NOT NULL
+    );
+";
+
+// Brings a database from the version before to the given version.
+// SCHEMA above always creates the newest version.
+const MIGRATIONS: &[(u32, &str)] = &[
+    (
+        2,
+        "ALTER TABLE spans ADD COLUMN title TEXT NOT NULL DEFAULT ''",
+    ),
+    (
+        3,
+        "ALTER TABLE spans ADD COLUMN class TEXT NOT NULL DEFAULT ''",
+    ),
+    (
+        4,
+        "ALTER TABLE spans ADD COLUMN category TEXT NOT NULL DEFAULT '';
+         ALTER TABLE spans ADD COLUMN score REAL NOT NULL DEFAULT 0;
+         ALTER TABLE daily ADD COLUMN score REAL NOT NULL DEFAULT 0;
+         UPDATE spans SET score = CASE is_prod WHEN 1 THEN 1.0 ELSE -1.0 END;
+         UPDATE daily SET score = CASE is_prod WHEN 1 THEN 1.0 ELSE -1.0 END;",
+    ),
+    (
+        5,
+        "ALTER TABLE spans ADD COLUMN profile TEXT NOT NULL DEFAULT 'default'",
+    ),
+    (
+        6,
+        "CREATE TABLE IF NOT EXISTS meta (
+             key   TEXT PRIMARY KEY,
+             value TEXT NOT NULL
+         )",
+    ),
+    (
+        7,
+        "CREATE TABLE IF NOT EXISTS open_span (
+             start    TEXT    NOT NULL,
+             end      TEXT    NOT NULL,
+             pid      INTEGER NOT NULL,
+             name     TEXT    NOT NULL,
+             class    TEXT    NOT NULL,
+             title    TEXT    NOT NULL,
+             category TEXT    NOT NULL,
+             score    REAL    NOT NULL,
+             profile  TEXT    NOT NULL
+         )",
+    ),
+    (
+        8,
+        "ALTER TABLE daily ADD COLUMN productive INTEGER NOT NULL DEFAULT 0;
+         ALTER TABLE daily ADD COLUMN weighted REAL NOT NULL DEFAULT 0;
+         UPDATE daily SET productive = CASE WHEN score > 0 THEN time ELSE 0 END,
+                          weighted = time * score;",
+    ),
+];
+
+// Adds the span to the log and its time to its day's totals.
+// score and is_prod are the last span's, productive and weighted
+// add up every span's.
+fn insert_span(conn: &Connection, span: &Span) -> rusqlite::Result<()> {
+    let totals = Totals::new(span.secs(), span.score);
+    conn.execute(
+        "INSERT INTO spans
+         (start, end, pid, name, class, title, category, score, is_prod, profile)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            span.start.to_rfc3339(),
+            span.end.to_rfc3339(),
+            span.pid,
+            span.name,
+            span.class,
+            span.title,
+            span.category,
+            span.score,
+            span.score > 0.0,
+            span.profile
+        ],
+    )?;
+    conn.execute(
+        "INSERT INTO daily (day, name, time, score, is_prod, productive, weighted)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+         ON CONFLICT (day, name) DO UPDATE
+         SET time = time + excluded.time,
+             score = excluded.score,
+             is_prod = excluded.is_prod,
+             productive = productive + excluded.productive,
+             weighted = weighted + excluded.weighted",
+        params![
+            span.start.date_naive().to_string(),
+            span.name,
+            totals.time as i64,
+            span.score,
+            span.score > 0.0,
+            totals.productive as i64,
+            totals.weighted
+        ],
+    )?;
+    Ok(())
+}
+
+fn span_from_row(r: &rusqlite::Row) -> rusqlite::Result<Span> {
+    Ok(Span {
+        start: r.get(0)?,
+        end: r.get(1)?,
+        pid: r.get(2)?,
+        name: r.get(3)?,
+        class: r.get(4)?,
+        title: r.get(5)?,
+        category: r.get(6)?,
+        score: r.get(7)?,
+        profile: r.get(8)?,
+    })
+}
+
+// Every name in the totals and the spans
+fn names(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt =
+        conn.prepare("SELECT DISTINCT name FROM daily UNION SELECT DISTINCT name FROM spans")?;
+    let rows = stmt.query_map([], |r| r.get(0))?;
+    rows.collect()
+}
+
+// Spans and per day totals in an embedded database.
+// WAL mode lets other processes read while the daemon writes.
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn new(share: &Path) -> RecorderResult<SqliteStorage> {
+        if !share.is_dir() {
+            return Err(RecorderError::PathDoesNotExistError(
+                share.to_string_lossy().into_owned(),
+            ));
+        }
+
+        let conn = Connection::open(share.join(DATABASE_FILE))?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.execute_batch(SCHEMA)?;
+
+        let version: Option<u32> = conn
+            .query_row("SELECT version FROM schema_version", [], |r| r.get(0))
+            .optional()?;
+        match version {
+            None => {
+                conn.execute(
+                    "INSERT INTO schema_version (version) VALUES (?1)",
+                    params![SCHEMA_VERSION],
+                )?;
+            }
+            Some(v) if v > SCHEMA_VERSION => {
+                return Err(RecorderError::SchemaVersionError(v, SCHEMA_VERSION));
+            }
+            Some(v) if v < SCHEMA_VERSION => {
+                let tx = conn.unchecked_transaction()?;
+                for (_, sql) in MIGRATIONS.iter().filter(|(to, _)| *to > v) {
+                    tx.execute_batch(sql)?;
+                }
+                tx.execute(
+                    "UPDATE schema_version SET version = ?1",
+                    params![SCHEMA_VERSION],
+                )?;
+                tx.commit()?;
+            }
+            Some(_) => {}
+        }
+
+        // A span left open by a crash ends where it was last written
+        let open = conn
+            .query_row(
+                "SELECT start, end, pid, name, class, title, category, score, profile
+                 FROM open_span",
+                [],
+                span_from_row,
+            )
+            .optional()?;
+        if let Some(span) = open {
+            let tx = conn.unchecked_transaction()?;
+            insert_span(&tx, &span)?;
+            tx.execute("DELETE FROM open_span", [])?;
+            tx.commit()?;
+            println!(
+                "Recovered {} seconds of {} that were open when the daemon stopped",
+                span.secs(),
+                span.name
+            );
+        }
+
+        Ok(SqliteStorage {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    // Can't be upgraded, so an old database has to be opened by the daemon first
+    pub fn read_only(share: &Path) -> RecorderResult<SqliteStorage> {
+        let path = share.join(DATABASE_FILE);
+        if !path.is_file() {
+            return Err(RecorderError::PathDoesNotExistError(
+                path.to_string_lossy().into_owned(),
+            ));
+        }
+
+        let conn = Connection::open_with_flags(
+            path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?;
+        let version: u32 =
+            conn.query_row("SELECT version FROM schema_version", [], |r| r.get(0))?;
+        if version != SCHEMA_VERSION {
+            return Err(RecorderError::SchemaVersionError(version, SCHEMA_VERSION));
+        }
+
+        Ok(SqliteStorage {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn load(&self) -> RecorderResult<(NaiveDate, ProcTimes)> {
+        let conn = self.conn.lock().unwrap();
+
+        let last_day: Option<NaiveDate> = conn
+            .query_row("SELECT MAX(day) FROM daily", [], |r| {
+                r.get::<_, Option<String>>(0)
+            })?
+            .and_then(|d| d.parse().ok());
+        let week = week_of(last_day.unwrap_or_else(|| Local::now().date_naive()));
+
+        let mut stmt = conn.prepare(
+            "SELECT name, SUM(time), SUM(productive), SUM(weighted)
+             FROM daily WHERE day >= ?1 GROUP BY name",
+        )?;
+        let rows = stmt.query_map(params![week.to_string()], |r| {
+            let totals = Totals {
+                time: r.get::<_, i64>(1)? as u64,
+                productive: r.get::<_, i64>(2)? as u64,
+                weighted: r.get(3)?,
+            };
+            Ok((r.get::<_, String>(0)?, totals))
+        })?;
+
+        let mut map = ProcTimes::new();
+        for row in rows {
+            let (name, totals) = row?;
+            map.insert(name, totals);
+        }
+        Ok((week, map))
+    }
+
+    // The open span is replaced in the same transaction
+    // the spans it turned into are added in
+    fn write(
+        &self,
+        spans: &[Span],
+        _proc_times: &ProcTimes,
+        open: Option<&Span>,
+    ) -> RecorderResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for span in spans {
+            insert_span(&tx, span)?;
+        }
+        tx.execute("DELETE FROM open_span", [])?;
+        if let Some(span) = open {
+            tx.execute(
+                "INSERT INTO open_span
+                 (start, end, pid, name, class, title, category, score, profile)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    span.start.to_rfc3339(),
+                    span.end.to_rfc3339(),
+                    span.pid,
+                    span.name,
+                    span.class,
+                    span.title,
+                    span.category,
+                    span.score,
+                    span.profile
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    // Starts are RFC 3339 so they compare by date as text
+    fn spans_since(&self, day: NaiveDate) -> RecorderResult<Vec<Span>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT start, end, pid, name, class, title, category, score, profile
+             FROM spans WHERE start >= ?1 ORDER BY start",
+        )?;
+        let rows = stmt.query_map(params![day.to_string()], span_from_row)?;
+        let spans = rows.collect::<Result<_, _>>()?;
+        Ok(spans)
+    }
+
+    fn rename(&self, rename: &dyn Fn(&str) -> Option<String>) -> RecorderResult<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+
+        let mut renamed = 0;
+        for old in names(&tx)? {
+            let new = match rename(&old) {
+                Some(n) => n,
+                None => continue,
+            };
+
+            tx.execute(
+                "INSERT INTO daily (day, name, time, score, is_prod, productive, weighted)
+                 SELECT day, ?2, time, score, is_prod, productive, weighted
+                 FROM daily WHERE name = ?1
+                 ON CONFLICT (day, name) DO UPDATE
+                 SET time = time + excluded.time,
+                     productive = productive + excluded.productive,
+                     weighted = weighted + excluded.weighted",
+                params![old, new],
+            )?;
+            tx.execute("DELETE FROM daily WHERE name = ?1", params![old])?;
+            tx.execute(
+                "UPDATE spans SET name = ?2 WHERE name = ?1",
+                params![old, new],
+            )?;
+            renamed += 1;
+        }
+
+        tx.commit()?;
+        Ok(renamed)
+    }
+
+    fn names(&self) -> RecorderResult<Vec<String>> {
+        Ok(names(&self.conn.lock().unwrap())?)
+    }
+
+    fn process_name(&self) -> RecorderResult<Option<ProcessName>> {
+        let conn = self.conn.lock().unwrap();
+        let name: Option<String> = conn
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'process_name'",
+                [],
+                |r| r.get(0),
+            )
+            .optional()?;
+        Ok(name.and_then(|n| ProcessName::from_name(&n)))
+    }
+
+    fn set_process_name(&self, strategy: ProcessName) -> RecorderResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO meta (key, value) VALUES ('process_name', ?1)
+             ON CONFLICT (key) DO UPDATE SET value = excluded.value",
+            params![strategy.name()],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs::{create_dir_all, remove_dir_all};
+    use std::path::PathBuf;
+
+    fn share(name: &str) -> PathBuf {
+        let share = std::env::temp_dir().join(format!("septem-{}-{}", name, std::process::id()));
+        let _ = remove_dir_all(&share);
+        create_dir_all(&share).unwrap();
+        share
+    }
+
+    fn span(name: &str, secs: i64) -> Span {
+        let start = Local::now() - chrono::Duration::seconds(secs);
+        Span {
+            start,
+            end: start + chrono::Duration::seconds(secs),
+            pid: 1,
+            name: name.to_owned(),
+            class: String::new(),
+            title: String::new(),
+            category: String::new(),
+            score: 1.0,
+            profile: "default".to_owned(),
+        }
+    }
+
+    #[test]
+    fn open_span_is_replaced_and_recovered_once() {
+        let share = share("sqlite-open");
+        let storage = SqliteStorage::new(&share).unwrap();
+        let mut open = span("vim", 60);
+        storage.write(&[], &ProcTimes::new(), Some(&open)).unwrap();
+        open.end += chrono::Duration::seconds(20);
+        storage.write(&[], &ProcTimes::new(), Some(&open)).unwrap();
+        assert_eq!(storage.load().unwrap().1.len(), 0);
+        drop(storage);
+
+        // Killed before it closed
+        let storage = SqliteStorage::new(&share).unwrap();
+        assert_eq!(storage.load().unwrap().1["vim"].time, 80);
+        drop(storage);
+
+        let storage = SqliteStorage::new(&share).unwrap();
+        assert_eq!(storage.load().unwrap().1["vim"].time, 80);
+        assert_eq!(
+            storage.spans_since(open.start.date_naive()).unwrap().len(),
+            1
+        );
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn load_adds_up_every_day_of_the_week() {
+        let share = share("sqlite-week");
+        let storage = SqliteStorage::new(&share).unwrap();
+        let at = |day: &str, secs: i64, score: f64| {
+            let mut s = span("slack", secs);
+            s.start = format!("{}T09:00:00", day)
+                .parse::<chrono::NaiveDateTime>()
+                .unwrap()
+                .and_local_timezone(Local)
+                .unwrap();
+            s.end = s.start + chrono::Duration::seconds(secs);
+            s.score = score;
+            s
+        };
+        let spans = [at("2026-10-13", 3600, 1.0), at("2026-10-14", 1800, -1.0)];
+        storage.write(&spans, &ProcTimes::new(), None).unwrap();
+
+        let (week, map) = storage.load().unwrap();
+        let mut expected = Totals::new(3600, 1.0);
+        expected.add(1800, -1.0);
+        assert_eq!(week, NaiveDate::from_ymd_opt(2026, 10, 12).unwrap());
+        assert_eq!(map["slack"], expected);
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn old_daily_totals_are_upgraded_with_their_score() {
+        let share = share("sqlite-upgrade");
+        let conn = Connection::open(share.join(DATABASE_FILE)).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE schema_version (version INTEGER NOT NULL);
+             INSERT INTO schema_version (version) VALUES (7);
+             CREATE TABLE daily (
+                 day     TEXT    NOT NULL,
+                 name    TEXT    NOT NULL,
+                 time    INTEGER NOT NULL,
+                 score   REAL    NOT NULL DEFAULT 0,
+                 is_prod INTEGER NOT NULL,
+                 PRIMARY KEY (day, name)
+             );",
+        )
+        .unwrap();
+        let today = Local::now().date_naive().to_string();
+        conn.execute(
+            "INSERT INTO daily (day, name, time, score, is_prod) VALUES (?1, 'vim', 60, 0.5, 1)",
+            params![today],
+        )
+        .unwrap();
+        drop(conn);
+
+        let storage = SqliteStorage::new(&share).unwrap();
+        assert_eq!(storage.load().unwrap().1["vim"], Totals::new(60, 0.5));
+        remove_dir_all(share).unwrap();
+    }
+
+    #[test]
+    fn rename_renames_the_totals_and_the_spans() {
+        let share = share("sqlite-rename");
+        let storage = SqliteStorage::new(&share).unwrap();
+        let (vim, mpv) = (span("vim", 60), span("mpv", 30));
+        let mut map = ProcTimes::new();
+        map.insert("vim".to_owned(), Totals::new(60, 1.0));
+        map.insert("mpv".to_owned(), Totals::new(30, 1.0));
+        storage.write(&[vim.clone(), mpv], &map, None).unwrap();
+
+        let renamed = storage
+            .rename(&|n| (n == "vim").then(|| "nvim".to_owned()))
+            .unwrap();
+        assert_eq!(renamed, 1);
+        let mut names = storage.names().unwrap();
+        names.sort();
+        assert_eq!(names, vec!["mpv", "nvim"]);
+        let spans = storage.spans_since(vim.start.date_naive()).unwrap();
+        let mut names: Vec<_> = spans.iter().map(|s| s.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["mpv", "nvim"]);
+        remove_dir_all(share).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+use crate::config::budget_config::Budget;
+use crate::config::profile_config::Profiles;
+use crate::config::{alert_config::AlertConfig, date_config::DateTimeConfig, Config};
+use crate::server::check_config;
+use crate::server::client::{Activity, ActivitySender, Command, Shared, Timeout};
+use crate::server::profiles;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::Local;
+use tokio::sync::{oneshot, Mutex};
+
+// Reads the config file again and hands it to the running clients.
+// Nothing changes unless the whole file passes every client's checks.
+// The idle watcher and where data is kept only change on restart.
+// Switching profiles hands the clients the profile's settings the same way.
+#[derive(Clone)]
+pub struct Reloader {
+    config_file: Option<String>,
+    sender: ActivitySender,
+    alert: Shared<AlertConfig>,
+    date: Shared<DateTimeConfig>,
+    budgets: Shared<Vec<Budget>>,
+    // Lets the date checker know its hours changed
+    date_reloaded: Timeout,
+    // and the profile scheduler its schedule
+    profiles_reloaded: Timeout,
+    profiles: Shared<Profiles>,
+    // Chosen by hand, None follows the schedule
+    chosen: Shared<Option<String>>,
+    active: Shared<String>,
+    // Where the chosen profile is remembered
+    state: PathBuf,
+    // One reload or switch at a time
+    lock: Arc<Mutex<()>>,
+}
+
+impl Reloader {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        config_file: Option<String>,
+        sender: ActivitySender,
+        alert: Shared<AlertConfig>,
+        date: Shared<DateTimeConfig>,
+        budgets: Shared<Vec<Budget>>,
+        date_reloaded: Timeout,
+        profiles_reloaded: Timeout,
+        profiles: Profiles,
+        chosen: Option<String>,
+        active: String,
+        state: PathBuf,
+    ) -> Reloader {
+        Reloader {
+            config_file,
+            sender,
+            alert,
+            date,
+            budgets,
+            date_reloaded,
+            profiles_reloaded,
+            profiles: Shared::new(profiles),
+            chosen: Shared::new(chosen),
+            active: Shared::new(active),
+            state,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn active(&self) -> String {
+        self.active.load()
+    }
+
+    pub fn chosen(&self) -> Option<String> {
+        self.chosen.load()
+    }
+
+    pub fn profiles(&self) -> Profiles {
+        self.profiles.load()
+    }
+
+    // Hands the clients the settings of the profile that should be active,
+    // unless it already is and nothing was reloaded
+    async fn apply(&self, reloaded: bool) -> Result<(), String> {
+        let profiles = self.profiles.load();
+        let chosen = self.chosen.load();
+        let (name, profile) = profiles.active(chosen.as_deref(), Local::now().naive_local());
+        if !reloaded && name == self.active.load() {
+            return Ok(());
+        }
+
+        // The recorder goes first as the only one that can still refuse
+        let (reply, answer) = oneshot::channel();
+        let command = Command::Reload(
+            Box::new(profile.recorder_config()),
+            self.budgets.load(),
+            name.to_owned(),
+        );
+        self.sender
+            .send(Activity::Command(command, reply))
+            .await
+            .map_err(|_| "The recorder has stopped".to_owned())?;
+        answer
+            .await
+            .map_err(|_| "The recorder has stopped".to_owned())??;
+
+        self.alert.store(profile.alert_config());
+        self.date.store(profile.date_config());
+        self.date_reloaded.notify_all();
+        if name != self.active.load() {
+            println!("Switched to the {} profile", name);
+            self.active.store(name);
+        }
+        Ok(())
+    }
+
+    pub async fn reload(&self) -> Result<(), String> {
+        let _lock = self.lock.lock().await;
+        let config = Config::new(self.config_file.clone()).map_err(|e| e.to_string())?;
+        check_config(&config).map_err(|e| e.to_string())?;
+
+        let old = (self.profiles.load(), self.budgets.load());
+        self.profiles.store(config.profiles());
+        self.budgets.store(config.budget_config());
+        if let Err(e) = self.apply(true).await {
+            self.profiles.store(old.0);
+            self.budgets.store(old.1);
+            return Err(e);
+        }
+        self.profiles_reloaded.notify_all();
+
+        // The schedule is followed again once the chosen profile is gone
+        if let Some(name) = self.chosen.load() {
+            if self.profiles.load().get(&name).is_none() {
+                self.chosen.store(None);
+                if let Err(e) = profiles::remember(&self.state, None) {
+                    println!("Failed to forget the chosen profile:\n{}", e);
+                }
+                println!(
+                    "The chosen {} profile is gone, following the schedule",
+                    name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    // Uses the profile until another is chosen, or the schedule with None
+    pub async fn choose(&self, name: Option<String>) -> Result<(), String> {
+        let _lock = self.lock.lock().await;
+        if let Some(n) = &name {
+            if self.profiles.load().get(n).is_none() {
+                return Err(profiles::ProfileError::UnknownProfileError(n.to_owned()).to_string());
+            }
+        }
+
+        let old = self.chosen.load();
+        self.chosen.store(name.clone());
+        if let Err(e) = self.apply(false).await {
+            self.chosen.store(old);
+            return Err(e);
+        }
+        if let Err(e) = profiles::remember(&self.state, name.as_deref()) {
+            println!("Failed to remember the chosen profile:\n{}", e);
+        }
+        Ok(())
+    }
+
+    pub async fn follow_schedule(&self) -> Result<(), String> {
+        let _lock = self.lock.lock().await;
+        self.apply(false).await
+    }
+}
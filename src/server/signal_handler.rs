@@ -1,4 +1,5 @@
 use crate::server::client::{Client, ClientResult, Running};
+use crate::server::reloader::Reloader;
 
 use futures::stream::StreamExt;
 use signal_hook::consts::signal::*;
@@ -22,17 +23,19 @@ type SignalResult<T> = Result<T, SignalError>;
 
 pub struct SignalHandler {
     running: Running,
+    reloader: Reloader,
     signals: Signals,
     handle: Handle,
 }
 
 impl SignalHandler {
-    pub fn new(running: Running) -> SignalResult<SignalHandler> {
+    pub fn new(running: Running, reloader: Reloader) -> SignalResult<SignalHandler> {
         let signals = Signals::new(&[SIGHUP, SIGTERM, SIGINT, SIGQUIT])?;
         let handle = signals.handle();
 
         Ok(SignalHandler {
             running,
+            reloader,
             signals,
             handle,
         })
@@ -49,7 +52,11 @@ impl Client for SignalHandler {
         let mut signals = self.signals.fuse();
         while let Some(sig) = signals.next().await {
             match sig {
-                SIGHUP | SIGTERM | SIGINT | SIGQUIT => {
+                SIGHUP => match self.reloader.reload().await {
+                    Ok(()) => println!("Reloaded the config"),
+                    Err(e) => println!("Kept the old config, the new one has an error:\n{}", e),
+                },
+                SIGTERM | SIGINT | SIGQUIT => {
                     break;
                 }
                 _ => {